@@ -1,5 +1,14 @@
+use std::cell::RefCell;
+
 use ollama_rs::generation::parameters::JsonSchema;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agent::AgentId,
+    governance::{Rule, RuleKind},
+    ledger::Goods,
+    names,
+};
 
 #[derive(JsonSchema, Deserialize, Debug)]
 pub struct LlmAction {
@@ -8,7 +17,7 @@ pub struct LlmAction {
     pub args: ActionArgs,
 }
 
-#[derive(JsonSchema, Deserialize, Debug)]
+#[derive(JsonSchema, Serialize, Deserialize, Clone, Debug)]
 pub enum Action {
     Work,
     MakeFood,
@@ -17,6 +26,15 @@ pub enum Action {
     Converse,
     Reproduce,
     Broadcast,
+    CallMeeting,
+    PostOffer,
+    PostBid,
+    AcceptOffer,
+    ProposeContract,
+    AcceptContract,
+    RejectContract,
+    ProposeRule,
+    Idle,
 }
 
 #[derive(JsonSchema, Deserialize, Debug)]
@@ -24,4 +42,547 @@ pub struct ActionArgs {
     pub who_to_interact_with: Option<String>,
     pub amount: Option<u32>,
     pub message: Option<String>,
+    pub price: Option<u32>,
+    pub order_id: Option<u32>,
+    pub give: Option<Goods>,
+    pub receive: Option<Goods>,
+    pub deadline: Option<u32>,
+    pub contract_id: Option<u32>,
+    pub participants: Option<Vec<String>>,
+    pub question: Option<String>,
+    pub rule: Option<RuleKind>,
+}
+
+// longest a contract can stay open, in steps
+pub const MAX_DEADLINE: u32 = 100;
+
+/// An action whose arguments have been checked, ready to be resolved.
+/// Everyone it involves is an `AgentId`, or a name once it's `named` for
+/// showing to people
+#[derive(Serialize, Clone, Debug)]
+pub enum ValidAction<Who = AgentId> {
+    Work,
+    MakeFood,
+    GiveMoney {
+        to: Who,
+        amount: u32,
+    },
+    GiveFood {
+        to: Who,
+        amount: u32,
+    },
+    Converse {
+        to: Who,
+        message: String,
+    },
+    Reproduce {
+        to: Who,
+        message: String,
+    },
+    Broadcast {
+        message: String,
+    },
+    // `with` is None for everyone
+    CallMeeting {
+        with: Option<Vec<Who>>,
+        agenda: String,
+        question: Option<String>,
+    },
+    PostOffer {
+        amount: u32,
+        price: u32,
+    },
+    PostBid {
+        amount: u32,
+        price: u32,
+    },
+    // amount defaults to the whole offer
+    AcceptOffer {
+        order_id: u32,
+        amount: Option<u32>,
+    },
+    // deadline is in steps from now
+    ProposeContract {
+        to: Who,
+        give: Goods,
+        receive: Goods,
+        deadline: u32,
+    },
+    AcceptContract {
+        contract_id: u32,
+    },
+    RejectContract {
+        contract_id: u32,
+    },
+    ProposeRule {
+        rule: Rule,
+        message: String,
+    },
+    Idle,
+}
+
+/// What an agent settled on for this step, and how it got there
+#[derive(Clone, Debug)]
+pub struct Decision {
+    // empty if we had to fall back
+    pub thinking: String,
+    pub action: ValidAction,
+    // why each rejected attempt was rejected
+    pub errors: Vec<String>,
+}
+
+impl ValidAction {
+    // only argument-less actions can be taken on someone's behalf
+    pub fn fallback(action: &Action) -> Self {
+        match action {
+            Action::Work => ValidAction::Work,
+            Action::MakeFood => ValidAction::MakeFood,
+            _ => ValidAction::Idle,
+        }
+    }
+
+    /// The same action with everyone in it called by `name`, for events
+    pub fn named(self, name: impl Fn(AgentId) -> String) -> ValidAction<String> {
+        match self {
+            ValidAction::Work => ValidAction::Work,
+            ValidAction::MakeFood => ValidAction::MakeFood,
+            ValidAction::GiveMoney { to, amount } => ValidAction::GiveMoney {
+                to: name(to),
+                amount,
+            },
+            ValidAction::GiveFood { to, amount } => ValidAction::GiveFood {
+                to: name(to),
+                amount,
+            },
+            ValidAction::Converse { to, message } => ValidAction::Converse {
+                to: name(to),
+                message,
+            },
+            ValidAction::Reproduce { to, message } => ValidAction::Reproduce {
+                to: name(to),
+                message,
+            },
+            ValidAction::Broadcast { message } => ValidAction::Broadcast { message },
+            ValidAction::CallMeeting {
+                with,
+                agenda,
+                question,
+            } => ValidAction::CallMeeting {
+                with: with.map(|with| with.into_iter().map(&name).collect()),
+                agenda,
+                question,
+            },
+            ValidAction::PostOffer { amount, price } => ValidAction::PostOffer { amount, price },
+            ValidAction::PostBid { amount, price } => ValidAction::PostBid { amount, price },
+            ValidAction::AcceptOffer { order_id, amount } => {
+                ValidAction::AcceptOffer { order_id, amount }
+            }
+            ValidAction::ProposeContract {
+                to,
+                give,
+                receive,
+                deadline,
+            } => ValidAction::ProposeContract {
+                to: name(to),
+                give,
+                receive,
+                deadline,
+            },
+            ValidAction::AcceptContract { contract_id } => {
+                ValidAction::AcceptContract { contract_id }
+            }
+            ValidAction::RejectContract { contract_id } => {
+                ValidAction::RejectContract { contract_id }
+            }
+            ValidAction::ProposeRule { rule, message } => {
+                ValidAction::ProposeRule { rule, message }
+            }
+            ValidAction::Idle => ValidAction::Idle,
+        }
+    }
+}
+
+/// What to do when an agent keeps producing garbage
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub fallback: Action,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            fallback: Action::Idle,
+        }
+    }
+}
+
+impl LlmAction {
+    /// Checks that every argument the action needs is present and sensible,
+    /// and works out who in `roster` any names mean. The error is meant to be
+    /// shown back to the agent, as are the notes that come with a valid action
+    /// about any names we had to guess at
+    pub fn validate(
+        self,
+        me: AgentId,
+        roster: &[(AgentId, String)],
+        departed: &[String],
+    ) -> Result<(ValidAction, Vec<String>), String> {
+        let ActionArgs {
+            who_to_interact_with,
+            amount,
+            message,
+            price,
+            order_id,
+            give,
+            receive,
+            deadline,
+            contract_id,
+            participants,
+            question,
+            rule,
+        } = self.args;
+        let action = self.action;
+
+        let living: Vec<_> = roster.iter().map(|(_, name)| name.clone()).collect();
+        let corrections = RefCell::new(Vec::new());
+        let resolve = |who: &str| {
+            let (id, found) = &roster[names::resolve(who, &living, departed)?];
+            if names::normalize(who) != names::normalize(found) {
+                corrections.borrow_mut().push(format!(
+                    "There is nobody called '{who}', so we assumed you meant {found}."
+                ));
+            }
+            Ok::<_, String>((*id, found.clone()))
+        };
+
+        let target = || {
+            let who = who_to_interact_with
+                .as_deref()
+                .map(str::trim)
+                .filter(|w| !w.is_empty())
+                .ok_or_else(|| format!("{action:?} requires who_to_interact_with"))?;
+
+            // tolerate a slip of the keyboard, but say so if it could be anyone
+            let (who, name) = resolve(who)?;
+            if who == me {
+                return Err(format!("You can't {action:?} with yourself"));
+            }
+
+            Ok((who, name))
+        };
+        let to = || target().map(|(who, _)| who);
+
+        let price = || match price {
+            Some(0) | None => Err(format!("{action:?} requires a price greater than 0")),
+            Some(p) => Ok(p),
+        };
+
+        let amount_or_all = amount;
+        let amount = || match amount {
+            Some(0) => Err(format!("{action:?} requires an amount greater than 0")),
+            Some(a) => Ok(a),
+            None => Err(format!("{action:?} requires an amount")),
+        };
+
+        let message = || {
+            message
+                .clone()
+                .filter(|m| !m.trim().is_empty())
+                .ok_or_else(|| format!("{action:?} requires a message"))
+        };
+
+        let valid = match &action {
+            Action::Work => ValidAction::Work,
+            Action::MakeFood => ValidAction::MakeFood,
+            Action::GiveMoney => ValidAction::GiveMoney {
+                to: to()?,
+                amount: amount()?,
+            },
+            Action::GiveFood => ValidAction::GiveFood {
+                to: to()?,
+                amount: amount()?,
+            },
+            Action::Converse => ValidAction::Converse {
+                to: to()?,
+                message: message()?,
+            },
+            Action::Reproduce => ValidAction::Reproduce {
+                to: to()?,
+                message: message()?,
+            },
+            Action::Broadcast => ValidAction::Broadcast {
+                message: message()?,
+            },
+            Action::CallMeeting => {
+                let mut with = vec![];
+                for who in participants.iter().flatten() {
+                    let (who, _) = resolve(who)?;
+                    if who != me && !with.contains(&who) {
+                        with.push(who);
+                    }
+                }
+                if with.is_empty() && participants.is_some_and(|p| !p.is_empty()) {
+                    return Err("You can't hold a meeting with only yourself".into());
+                }
+
+                ValidAction::CallMeeting {
+                    with: Some(with).filter(|w| !w.is_empty()),
+                    agenda: message()?,
+                    question: question.filter(|q| !q.trim().is_empty()),
+                }
+            }
+            Action::PostOffer => ValidAction::PostOffer {
+                amount: amount()?,
+                price: price()?,
+            },
+            Action::PostBid => ValidAction::PostBid {
+                amount: amount()?,
+                price: price()?,
+            },
+            Action::AcceptOffer => ValidAction::AcceptOffer {
+                order_id: order_id.ok_or("AcceptOffer requires an order_id")?,
+                amount: amount_or_all.filter(|a| *a > 0),
+            },
+            Action::ProposeContract => {
+                let give = give.unwrap_or_default();
+                let receive = receive.unwrap_or_default();
+                if give.is_empty() && receive.is_empty() {
+                    return Err("ProposeContract requires something to give or receive".into());
+                }
+
+                ValidAction::ProposeContract {
+                    to: to()?,
+                    give,
+                    receive,
+                    deadline: deadline.filter(|d| (1..=MAX_DEADLINE).contains(d)).ok_or(
+                        format!("ProposeContract requires a deadline between 1 and {MAX_DEADLINE}"),
+                    )?,
+                }
+            }
+            Action::AcceptContract => ValidAction::AcceptContract {
+                contract_id: contract_id.ok_or("AcceptContract requires a contract_id")?,
+            },
+            Action::RejectContract => ValidAction::RejectContract {
+                contract_id: contract_id.ok_or("RejectContract requires a contract_id")?,
+            },
+            Action::ProposeRule => ValidAction::ProposeRule {
+                rule: match rule.ok_or("ProposeRule requires a rule")? {
+                    RuleKind::FoodTax => Rule::FoodTax {
+                        percent: amount_or_all
+                            .filter(|p| *p <= 100)
+                            .ok_or("A FoodTax rule requires an amount between 0 and 100")?,
+                    },
+                    RuleKind::Exile => {
+                        let (who, name) = target()?;
+                        Rule::Exile { who, name }
+                    }
+                },
+                message: message()?,
+            },
+            Action::Idle => ValidAction::Idle,
+        };
+
+        Ok((valid, corrections.into_inner()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn validate(action: Value) -> Result<(ValidAction, Vec<String>), String> {
+        let roster: Vec<_> = ["Ann", "Bob", "Gussie"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (AgentId(i as u32), name.to_string()))
+            .collect();
+        let departed = vec!["Jon".to_string()];
+        serde_json::from_value::<LlmAction>(action)
+            .unwrap()
+            .validate(AgentId(0), &roster, &departed)
+    }
+
+    fn act(action: &str, args: Value) -> Value {
+        json!({"thinking": "", "action": action, "args": args})
+    }
+
+    #[test]
+    fn argument_less_actions_need_nothing() {
+        for action in ["Work", "MakeFood", "Idle"] {
+            assert!(validate(act(action, json!({}))).is_ok(), "{action}");
+        }
+    }
+
+    #[test]
+    fn missing_arguments_are_explained() {
+        let err = validate(act("GiveFood", json!({"amount": 1}))).unwrap_err();
+        assert!(err.contains("who_to_interact_with"), "{err}");
+
+        let err = validate(act(
+            "GiveFood",
+            json!({"who_to_interact_with": "Bob", "amount": 0}),
+        ))
+        .unwrap_err();
+        assert!(err.contains("greater than 0"), "{err}");
+
+        assert!(validate(act("Broadcast", json!({"message": "  "}))).is_err());
+        assert!(validate(act("PostBid", json!({"amount": 1}))).is_err());
+        assert!(validate(act("AcceptContract", json!({}))).is_err());
+        assert!(validate(act("ProposeRule", json!({"message": "hi"}))).is_err());
+    }
+
+    #[test]
+    fn only_argument_less_actions_are_fallbacks() {
+        assert!(matches!(
+            ValidAction::fallback(&Action::Work),
+            ValidAction::Work
+        ));
+        assert!(matches!(
+            ValidAction::fallback(&Action::MakeFood),
+            ValidAction::MakeFood
+        ));
+        assert!(matches!(
+            ValidAction::fallback(&Action::GiveFood),
+            ValidAction::Idle
+        ));
+    }
+
+    #[test]
+    fn contracts_need_goods_and_a_bounded_deadline() {
+        let contract = |deadline: u32| {
+            act(
+                "ProposeContract",
+                json!({"who_to_interact_with": "Bob", "give": {"food": 1}, "deadline": deadline}),
+            )
+        };
+        assert!(validate(contract(1)).is_ok());
+        assert!(validate(contract(MAX_DEADLINE)).is_ok());
+        assert!(validate(contract(0)).is_err());
+        assert!(validate(contract(MAX_DEADLINE + 1)).is_err());
+
+        let err = validate(act(
+            "ProposeContract",
+            json!({"who_to_interact_with": "Bob", "deadline": 3}),
+        ))
+        .unwrap_err();
+        assert!(err.contains("something to give or receive"), "{err}");
+    }
+
+    #[test]
+    fn nobody_can_target_themselves() {
+        let err = validate(act(
+            "GiveMoney",
+            json!({"who_to_interact_with": "ann", "amount": 1}),
+        ))
+        .unwrap_err();
+        assert!(err.contains("yourself"), "{err}");
+    }
+
+    #[test]
+    fn guessed_names_come_with_a_note() {
+        let (action, corrections) = validate(act(
+            "GiveFood",
+            json!({"who_to_interact_with": "Gusie", "amount": 2}),
+        ))
+        .unwrap();
+        assert!(matches!(
+            action,
+            ValidAction::GiveFood {
+                to: AgentId(2),
+                amount: 2
+            }
+        ));
+        assert_eq!(corrections.len(), 1);
+        assert!(
+            corrections[0].contains("meant Gussie"),
+            "{}",
+            corrections[0]
+        );
+
+        let (_, corrections) = validate(act(
+            "GiveFood",
+            json!({"who_to_interact_with": "bob", "amount": 2}),
+        ))
+        .unwrap();
+        assert!(corrections.is_empty());
+    }
+
+    #[test]
+    fn departed_names_are_rejected() {
+        let err = validate(act(
+            "Converse",
+            json!({"who_to_interact_with": "Jon", "message": "hi"}),
+        ))
+        .unwrap_err();
+        assert!(err.contains("Jon has died"), "{err}");
+    }
+
+    #[test]
+    fn meetings_resolve_participants() {
+        let (action, _) = validate(act(
+            "CallMeeting",
+            json!({"participants": ["bob", "Ann", "Bob"], "message": "hi", "question": " "}),
+        ))
+        .unwrap();
+        let ValidAction::CallMeeting { with, question, .. } = action else {
+            panic!("{action:?}");
+        };
+        assert_eq!(with, Some(vec![AgentId(1)]));
+        assert_eq!(question, None);
+
+        let (action, _) = validate(act("CallMeeting", json!({"message": "hi"}))).unwrap();
+        assert!(matches!(
+            action,
+            ValidAction::CallMeeting { with: None, .. }
+        ));
+
+        let err = validate(act(
+            "CallMeeting",
+            json!({"participants": ["Ann"], "message": "hi"}),
+        ))
+        .unwrap_err();
+        assert!(err.contains("only yourself"), "{err}");
+    }
+
+    #[test]
+    fn rules_are_checked() {
+        let rule = |args: Value| validate(act("ProposeRule", args));
+
+        let (action, _) = rule(json!({"rule": "FoodTax", "amount": 0, "message": "hi"})).unwrap();
+        assert!(matches!(
+            action,
+            ValidAction::ProposeRule {
+                rule: Rule::FoodTax { percent: 0 },
+                ..
+            }
+        ));
+        assert!(rule(json!({"rule": "FoodTax", "amount": 101, "message": "hi"})).is_err());
+
+        let (action, _) =
+            rule(json!({"rule": "Exile", "who_to_interact_with": "Bob", "message": "hi"})).unwrap();
+        assert!(matches!(
+            action,
+            ValidAction::ProposeRule { rule: Rule::Exile { who: AgentId(1), ref name }, .. } if name == "Bob"
+        ));
+    }
+
+    #[test]
+    fn named_actions_call_everyone_by_name() {
+        let (action, _) = validate(act(
+            "CallMeeting",
+            json!({"participants": ["Bob", "Gussie"], "message": "hi"}),
+        ))
+        .unwrap();
+        let names = ["Ann", "Bob", "Gussie"];
+        let ValidAction::CallMeeting { with, .. } = action.named(|id| names[id.0 as usize].into())
+        else {
+            panic!("not a meeting");
+        };
+        assert_eq!(with, Some(vec!["Bob".to_string(), "Gussie".to_string()]));
+    }
 }
@@ -1,29 +1,74 @@
-use faker_rand::en_us::names::FirstName;
-use ollama_rs::{
-    generation::{
-        chat::{request::ChatMessageRequest, ChatMessage},
-        options::GenerationOptions,
-        parameters::{FormatType, JsonStructure},
-    },
-    Ollama,
+use std::{
+    fmt::{self, Display},
+    sync::Arc,
 };
+
+use ollama_rs::generation::chat::ChatMessage;
 use rand::*;
 use schemars::JsonSchema;
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    action::{Decision, LlmAction, ValidAction},
+    backend::{self, ChatBackend, ChatOptions},
+    config::Config,
+    episodes::EpisodicMemory,
+    event::DeathCause,
+    inheritance,
+    ledger::Resource,
+};
 
-use crate::action::LlmAction;
+/// Who an agent is, for good. Names can repeat across generations (with a
+/// suffix), ids never do
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct AgentId(pub u32);
 
-pub const MODEL: &str = "llama3.2:3b";
+impl Display for AgentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
-#[derive(Clone, Debug)]
+pub const TRAITS: [&str; 5] = [
+    "honesty",
+    "socialness",
+    "selfishness",
+    "compassion",
+    "food_ability",
+];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Agent {
+    // never reused, unlike names
+    pub id: AgentId,
     pub name: String,
-    pub ollama: Ollama,
+    // None for the founders
+    pub parents: Option<[AgentId; 2]>,
+    // founders are generation 0, their children 1, etc.
+    pub generation: u32,
+    // step of our latest child
+    #[serde(default)]
+    pub last_child: Option<u32>,
+    // neither is saved in snapshots; both get reattached on load
+    #[serde(skip, default = "backend::detached")]
+    pub backend: Arc<dyn ChatBackend>,
+    #[serde(skip)]
+    pub config: Arc<Config>,
 
     pub money: u32,
     pub age: u32,
     pub food: u32,
     pub history: Vec<ChatMessage>,
+    // malformed or invalid responses so far
+    pub errors: u32,
+    // summary of history that has been compacted away; kept right after the
+    // system prompt once there is any
+    #[serde(default)]
+    pub memories: String,
+    // what stood out, recalled into step prompts when relevant
+    #[serde(default)]
+    pub episodes: EpisodicMemory,
 
     // attributes (0-10)
     pub honesty: f32,
@@ -34,6 +79,8 @@ pub struct Agent {
 }
 
 impl Agent {
+    // rebuilt before every decision, so it always reflects the living roster
+    // and our current stats
     fn system_prompt(&self, all_names: &[String]) -> String {
         let names_formatted = all_names
             .iter()
@@ -42,6 +89,33 @@ impl Agent {
             .collect::<Vec<_>>()
             .join("\n");
         let money_ability = 10.0 - self.food_ability;
+        let (contracts, contract_actions) = if self.config.contracts.enabled {
+            (
+                "If you don't want to rely on trust, you can propose a contract instead. Whatever you offer is held in escrow as soon as you propose, and the trade only happens if the other person accepts before the deadline - otherwise you get it back.\n\n",
+                "- ProposeContract(who_to_interact_with, give, receive, deadline) - propose a binding trade: you give the food/money in give, they give the food/money in receive. They have deadline steps to accept\n- AcceptContract(contract_id) - accept a contract proposed to you, completing the trade\n- RejectContract(contract_id) - reject a contract proposed to you\n",
+            )
+        } else {
+            ("", "")
+        };
+
+        let governance = if self.config.governance.enabled {
+            "- ProposeRule(rule, amount, who_to_interact_with, message) - propose a rule for the whole community, which everyone votes on next step and must follow if it passes. rule is FoodTax (amount percent of all food anyone makes goes to the community pool, replacing any earlier tax) or Exile (who_to_interact_with has to leave the community, forfeiting everything they own to the community pool)\n"
+        } else {
+            ""
+        };
+
+        let rules = &self.config.reproduction;
+        let (proposer_share, partner_share) = rules.shares();
+        let mut family = format!(
+            "Having a baby costs {} in total: {proposer_share} from whoever proposes and {partner_share} from their partner, and the baby starts out with it. Both parents must be between {} and {} steps old, and nobody can have another baby within {} steps of their last.",
+            rules.cost, rules.min_age, rules.max_age, rules.cooldown
+        );
+        if self.is_child() {
+            family.push_str(&format!(
+                " You are still a child until age {}, so you can't Work or MakeFood yet. You depend on your parents and others giving you food.",
+                rules.childhood
+            ));
+        }
 
         format!(
             r#"
@@ -56,20 +130,29 @@ Compassion: {}/10
 Ability to make food: {}/10
 Ability to make money: {}/10
 
-Every step, you can take an action. You will also consume one food per action. Currently you have {} foods. If you run out of food, you will die. You can only have a maximum of 19 foods. Making food beyond this will be discarded and is a waste. Wasting food is VERY BAD. Also, you will only live to be about 80-100 steps old. You are currently age 0 steps.
+Every step, you can take an action. You will also consume one food per action. Currently you have {} foods. If you run out of food, you will die. You can only have a maximum of {} foods. Making food beyond this will be discarded and is a waste. Wasting food is VERY BAD. Also, you will only live to be about {} steps old. You are currently age {} steps.
+
+If you want to trade, use messages to try to set up a deal with another person. Then you can each give food/money to each other. Giving takes the food/money out of your own supply, and you can't give more than you have. Keep in mind the other person can always fall through on their end of the deal!
+
+{}There is also a market where food is bought and sold for money. Food or money you put into an order is held by the market until the order is filled, or returned to you if nobody takes it within a few steps. Matching bids and offers are settled at the end of every step at a single price for everyone.
 
-If you want to trade, use messages to try to set up a deal with another person. Then you can each give food/money to each other. Keep in mind the other person can always fall through on their end of the deal!
+Estates nobody inherits, food taxes and anything that won't fit in someone's pantry go into a community pool, which is shared out equally among everyone at the end of every step.
 
-You want to reproduce whenever possible. Reproduction adds new people to the community who can be interacted with. Your goal should be to keep the community alive, and reproduction is an easy way to do this.
+You want to reproduce whenever possible. Reproduction adds new people to the community who can be interacted with. Your goal should be to keep the community alive, and reproduction is an easy way to do this. {}
 
-You can take the following Actions. Pay attention to the arguments - they are required, not optional. If you forget an argument, you will die!
+You can take the following Actions. Pay attention to the arguments - they are required, not optional. If you forget an argument, your action will be rejected!
 - Work - get {} money for doing work
 - MakeFood(amount) - Make {} food
 - GiveMoney(who_to_interact_with, amount) - give money to another person
 - GiveFood(who_to_interact_with, amount) - give food to another person
 - Converse(who_to_interact_with, message) - send a message to a single other person
 - Broadcast(message) - send a message to every person
-- Reproduce(who_to_interact_with, message) - propose to have a baby with another person
+- CallMeeting(message, participants, question) - call a meeting about message with a list of participants, or everyone if participants is empty. Everyone takes turns speaking for {} rounds. If you give a yes/no question, everyone votes on it at the end
+- PostOffer(amount, price) - offer to sell amount food on the market for price dollars each
+- PostBid(amount, price) - offer to buy amount food on the market for up to price dollars each
+- AcceptOffer(order_id, amount) - immediately buy food from an open offer on the market at its price. amount is optional and defaults to the whole offer
+{}{}- Reproduce(who_to_interact_with, message) - propose to have a baby with another person
+- Idle - do nothing
 "#,
             self.name,
             names_formatted,
@@ -80,48 +163,244 @@ You can take the following Actions. Pay attention to the arguments - they are re
             self.food_ability,
             money_ability,
             self.food,
+            self.config.agent.max_food,
+            self.config.agent.death_age,
+            self.age,
+            contracts,
+            family,
             money_ability,
             self.food_ability,
+            self.config.meetings.rounds,
+            contract_actions,
+            governance,
         )
     }
 
-    pub async fn step(&mut self) -> anyhow::Result<LlmAction> {
-        let res = self
-            .ollama
-            .send_chat_messages_with_history(
-                &mut self.history,
-                ChatMessageRequest::new(
-                    MODEL.to_string(),
-                    vec![ChatMessage::user(format!(
-                        "Currently you have {} food (max 19, dead at 0), {} dollars, and are age {} steps. What action would you like to take?",
-                        self.food, self.money, self.age
-                    ))],
+    // `rules` are the community's rules, `market` is the current state of the
+    // market and `relationships` how we feel about everyone, all shown
+    // alongside our own stats. `departed` are everyone who has died or been
+    // exiled, so we can tell when they're asked for
+    pub async fn step(
+        &mut self,
+        roster: &[(AgentId, String)],
+        departed: &[String],
+        rules: &str,
+        market: &str,
+        relationships: &str,
+        time: u32,
+    ) -> anyhow::Result<Decision> {
+        // the roster and our stats have moved on since it was last written
+        let names: Vec<_> = roster.iter().map(|(_, name)| name.clone()).collect();
+        self.history[0] = ChatMessage::system(self.system_prompt(&names));
+
+        let recalled = self.recall(market, time);
+
+        let mut prompt = ChatMessage::user(format!(
+            "{rules}\n{market}\n{relationships}\n{recalled}Currently you have {} food (max {}, dead at 0), {} dollars, and are age {} steps. What action would you like to take?",
+            self.food,
+            self.config.agent.max_food,
+            self.money,
+            self.age
+        ));
+
+        let mut errors = Vec::new();
+
+        for _ in 0..=self.config.retry.max_retries {
+            let res = self
+                .backend
+                .chat_with_history(
+                    &mut self.history,
+                    prompt.clone(),
+                    &ChatOptions::from_config(&self.config).json::<LlmAction>(),
                 )
-                    .format(FormatType::StructuredJson(JsonStructure::new::<LlmAction>()))
-                    .options(GenerationOptions::default().temperature(0.9).num_ctx(16_384)),
-            )
-            .await
-            .unwrap();
+                .await;
+
+            let res = match res {
+                Ok(res) => res,
+                Err(e) => {
+                    // nothing came back, so ask the same thing again
+                    self.backend_failed(&e);
+                    errors.push(format!("The backend failed ({e})"));
+                    continue;
+                }
+            };
+
+            let err = match serde_json::from_str::<LlmAction>(&res) {
+                Ok(action) => {
+                    println!("[DEBUG] {}: (thinking) {}", self.name, action.thinking);
+                    println!(
+                        "[DEBUG] {}: took action {:?} with params {:?}",
+                        self.name, action.action, action.args
+                    );
+
+                    let thinking = action.thinking.clone();
+                    match action
+                        .validate(self.id, roster, departed)
+                        .and_then(|(a, corrections)| Ok((self.allowed(a)?, corrections)))
+                    {
+                        Ok((action, corrections)) => {
+                            if !corrections.is_empty() {
+                                println!("[DEBUG] {}: {}", self.name, corrections.join(" "));
+                                self.history
+                                    .push(ChatMessage::system(corrections.join(" ")));
+                            }
+                            return Ok(Decision {
+                                thinking,
+                                action,
+                                errors,
+                            });
+                        }
+                        Err(e) => e,
+                    }
+                }
+                Err(e) => format!("Your response could not be understood ({e})"),
+            };
+
+            println!("[DEBUG] {}: invalid action: {}", self.name, err);
+            self.errors += 1;
+
+            prompt = ChatMessage::system(format!(
+                "Your last action was invalid: {err}. Please choose an action again, remembering to fill in every argument it needs."
+            ));
+            errors.push(err);
+        }
 
-        let action = serde_json::from_str(&res.message.content)?;
+        // the configured fallback is held to the same limits as a chosen action
+        let fallback = self
+            .allowed(ValidAction::fallback(&self.config.retry.fallback))
+            .unwrap_or(ValidAction::Idle);
+        println!(
+            "[DEBUG] {}: gave up, falling back to {:?}",
+            self.name, fallback
+        );
+        self.history.push(ChatMessage::system(format!(
+            "You failed to choose a valid action, so you did {:?} instead.",
+            fallback
+        )));
 
-        Ok(action)
+        Ok(Decision {
+            thinking: String::new(),
+            action: fallback,
+            errors,
+        })
     }
 
-    pub fn new_random(ollama: Ollama, all_names: &[String], name: String) -> Self {
+    /// Folds everything but the system prompt and the last `memory.window`
+    /// messages into `memories`, so old context doesn't silently fall out of
+    /// the model's window
+    pub async fn compact(&mut self) -> anyhow::Result<()> {
+        let pinned = if self.memories.is_empty() { 1 } else { 2 };
+        let window = self.config.memory.window;
+        if self.history.len() <= pinned + window {
+            return Ok(());
+        }
+
+        let old = pinned..self.history.len() - window;
+        let transcript = self.history[old.clone()]
+            .iter()
+            .map(|m| format!("{:?}: {}", m.role, m.content))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let previous = if self.memories.is_empty() {
+            "You have no memories yet.".to_string()
+        } else {
+            format!("Your memories so far:\n{}", self.memories)
+        };
+        let messages = [
+            self.history[0].clone(),
+            ChatMessage::user(format!(
+                "{previous}\n\nThis is what has happened since:\n{transcript}\n\nWrite your memories again, including what happened since. Keep everything you will need later - deals, promises, debts, who you trust, who has died - and leave out the rest. Be brief."
+            )),
+        ];
+
+        let res = self
+            .backend
+            .chat(&messages, &ChatOptions::from_config(&self.config))
+            .await;
+
+        // keep everything as it was and try again next time
+        self.memories = match res {
+            Ok(memories) => memories,
+            Err(e) => {
+                println!("[DEBUG] {}: couldn't compact memories: {e:#}", self.name);
+                return Ok(());
+            }
+        };
+
+        println!(
+            "[DEBUG] {}: compacted {} messages into memories",
+            self.name,
+            old.len()
+        );
+        self.history.drain(old);
+
+        let memories = ChatMessage::system(format!("Your memories:\n{}", self.memories));
+        if pinned == 2 {
+            self.history[1] = memories;
+        } else {
+            self.history.insert(1, memories);
+        }
+
+        Ok(())
+    }
+
+    pub fn remember(&mut self, time: u32, text: String, importance: f32) {
+        self.episodes
+            .record(time, text, importance, self.config.episodes.capacity);
+    }
+
+    // Episodes relevant to what's been going on lately, ready to go in a prompt
+    fn recall(&self, market: &str, time: u32) -> String {
+        let mut query = market.to_string();
+        for m in self.history.iter().rev().take(6) {
+            query.push('\n');
+            query.push_str(&m.content);
+        }
+
+        let episodes = self
+            .episodes
+            .recall(&query, time, self.config.episodes.top_k);
+        if episodes.is_empty() {
+            return String::new();
+        }
+
+        let mut recalled = "Things you remember:\n".to_string();
+        for e in episodes {
+            recalled.push_str(&format!("- (step {}) {}\n", e.time, e.text));
+        }
+        recalled
+    }
+
+    pub fn new_random(
+        backend: Arc<dyn ChatBackend>,
+        config: Arc<Config>,
+        all_names: &[String],
+        id: AgentId,
+        name: String,
+        rng: &mut impl Rng,
+    ) -> Self {
         let mut a = Agent {
-            ollama,
+            backend,
 
+            id,
             name,
-            money: 10,
+            parents: None,
+            generation: 0,
+            last_child: None,
+            money: config.agent.starting_money,
             age: 0,
-            food: 5,
+            food: config.agent.starting_food,
             history: vec![],
-            honesty: random::<f32>() * 10.0,
-            socialness: random::<f32>() * 10.0,
-            selfishness: random::<f32>() * 10.0,
-            compassion: random::<f32>() * 10.0,
-            food_ability: thread_rng().gen_range(0.0..=10.0),
+            errors: 0,
+            memories: String::new(),
+            episodes: EpisodicMemory::default(),
+            honesty: rng.gen::<f32>() * 10.0,
+            socialness: rng.gen::<f32>() * 10.0,
+            selfishness: rng.gen::<f32>() * 10.0,
+            compassion: rng.gen::<f32>() * 10.0,
+            food_ability: rng.gen_range(0.0..=10.0),
+            config,
         };
 
         a.history
@@ -130,51 +409,115 @@ You can take the following Actions. Pay attention to the arguments - they are re
         a
     }
 
-    pub fn give_food(&mut self, amount: u32, sender: &String) {
-        self.food += amount;
-        self.food = self.food.clamp(0, 20);
-        self.history.push(ChatMessage::system(format!(
-            "You have been given {} food by {}",
-            amount, sender
-        )));
+    // in the same order as TRAITS
+    pub fn traits(&self) -> [f32; 5] {
+        [
+            self.honesty,
+            self.socialness,
+            self.selfishness,
+            self.compassion,
+            self.food_ability,
+        ]
     }
 
-    pub fn give_money(&mut self, amount: u32, sender: &String) {
-        self.money += amount;
+    pub fn is_child(&self) -> bool {
+        self.parents.is_some() && self.age < self.config.reproduction.childhood
+    }
+
+    // Rules that depend on who we are rather than what we asked for
+    fn allowed(&self, action: ValidAction) -> Result<ValidAction, String> {
+        match action {
+            ValidAction::Work | ValidAction::MakeFood if self.is_child() => Err(format!(
+                "You are a child until age {}, so you can't {action:?} yet. Ask your parents for food instead",
+                self.config.reproduction.childhood
+            )),
+            action => Ok(action),
+        }
+    }
+
+    pub fn balance(&self, resource: Resource) -> u32 {
+        match resource {
+            Resource::Food => self.food,
+            Resource::Money => self.money,
+        }
+    }
+
+    // callers are expected to check the balance first
+    pub fn debit(&mut self, resource: Resource, amount: u32) {
+        match resource {
+            Resource::Food => self.food -= amount,
+            Resource::Money => self.money -= amount,
+        }
+    }
+
+    pub fn credit(&mut self, resource: Resource, amount: u32) {
+        match resource {
+            Resource::Food => {
+                self.food += amount;
+                self.food = self.food.clamp(0, self.config.agent.max_food);
+            }
+            Resource::Money => self.money += amount,
+        }
+    }
+
+    pub fn give(&mut self, resource: Resource, amount: u32, sender: &String) {
+        self.credit(resource, amount);
         self.history.push(ChatMessage::system(format!(
-            "You have been given ${} by {}",
-            amount, sender
+            "You have been given {} by {}",
+            resource.amount(amount),
+            sender
         )));
     }
 
     pub fn make_food(&mut self) {
         self.food += self.food_ability as u32;
-        self.food = self.food.clamp(0, 20);
+        self.food = self.food.clamp(0, self.config.agent.max_food);
     }
 
     pub fn work(&mut self) {
         self.money += 10 - self.food_ability as u32;
     }
 
-    pub async fn send_msg(&mut self, msg: String, sender: &String) -> String {
-        println!("[DEBUG] {} -> {}: {}", sender, self.name, msg);
-
-        let res = self
-            .ollama
-            .send_chat_messages_with_history(
-                &mut self.history,
-                ChatMessageRequest::new(
-                MODEL.to_string(),
-                vec![ChatMessage::user(format!(r#"{} has decided to chat! They said '{msg}' What would you like to say to them?"#, sender)
-                )]).format(FormatType::StructuredJson(JsonStructure::new::<MessageReply>())))
-            .await
-            .unwrap();
-
-        let msg: MessageReply = serde_json::from_str(&res.message.content).unwrap();
+    /// Says the next thing in a conversation with `partner`, given the
+    /// transcript so far, and whether it's goodbye. None if we never managed
+    /// to produce a reply
+    pub async fn converse(
+        &mut self,
+        partner: &str,
+        transcript: &str,
+    ) -> anyhow::Result<Option<(String, bool)>> {
+        let reply: Option<MessageReply> = self
+            .ask_aside(ChatMessage::user(format!(
+                "You are talking with {partner}. So far:\n{transcript}What would you like to say to them? Set end_conversation to true if you are saying goodbye."
+            )))
+            .await?;
+
+        Ok(reply.map(|r| (r.message, r.end_conversation)))
+    }
 
-        println!("[DEBUG] {} -> {}: {}", self.name, sender, msg.message);
+    /// Takes a turn speaking at a meeting `chair` called about `agenda`
+    pub async fn speak(
+        &mut self,
+        chair: &str,
+        agenda: &str,
+        transcript: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let reply: Option<MessageReply> = self
+            .ask_aside(ChatMessage::user(format!(
+                "You are at a meeting {chair} called about '{agenda}'. So far:\n{transcript}It's your turn to speak. What would you like to say to everyone?"
+            )))
+            .await?;
+
+        Ok(reply.map(|r| r.message))
+    }
 
-        msg.message
+    /// Votes yes (true) or no on `question`, after `context`. None if we
+    /// never managed to produce an answer
+    pub async fn vote(&mut self, question: &str, context: &str) -> anyhow::Result<Option<bool>> {
+        self.ask_aside(ChatMessage::user(format!(
+            "{context}Everyone is now voting on: '{question}' Do you vote yes? Respond true or false."
+        )))
+        .await
     }
 
     pub async fn listen(&mut self, msg: String, sender: &String) {
@@ -187,60 +530,120 @@ You can take the following Actions. Pay attention to the arguments - they are re
     pub async fn propose(&mut self, msg: String, sender: &String) -> anyhow::Result<bool> {
         println!("================ REPRODUCTION ATTEMPT ======================");
 
-        let res = self
-            .ollama
-            .send_chat_messages_with_history(
-                &mut self.history,
-                ChatMessageRequest::new(
-                MODEL.to_string(),
-                vec![ChatMessage::user(format!(r#"{} has proposed to reproduce! They said '{msg}' Do you accept? Respond true or false."#, sender)
-                )])
-                .format(FormatType::StructuredJson(JsonStructure::new::<bool>()))
+        // an unanswerable proposal is a rejection
+        let action = self
+            .ask(
+                ChatMessage::user(format!(r#"{} has proposed to reproduce! They said '{msg}' Do you accept? Respond true or false."#, sender)),
             )
-            .await
-            .unwrap();
-
-        let action = serde_json::from_str(&res.message.content)?;
+            .await?
+            .unwrap_or(false);
 
         dbg!(action);
 
         Ok(action)
     }
 
+    // Like `ask`, but on a copy of the history, for things like
+    // conversations and meetings whose transcript is added as a whole later
+    async fn ask_aside<T: JsonSchema + DeserializeOwned>(
+        &mut self,
+        prompt: ChatMessage,
+    ) -> anyhow::Result<Option<T>> {
+        let history = self.history.clone();
+        let res = self.ask(prompt).await;
+        self.history = history;
+
+        res
+    }
+
+    // A request that got no reply at all counts against us like a malformed
+    // one. Takes back the prompt `chat_with_history` left unanswered
+    fn backend_failed(&mut self, e: &anyhow::Error) {
+        println!("[DEBUG] {}: backend error: {e:#}", self.name);
+        self.errors += 1;
+        self.history.pop();
+    }
+
+    // Asks for a structured response, retrying on malformed output or a
+    // failed request
+    async fn ask<T: JsonSchema + DeserializeOwned>(
+        &mut self,
+        mut prompt: ChatMessage,
+    ) -> anyhow::Result<Option<T>> {
+        for _ in 0..=self.config.retry.max_retries {
+            let res = self
+                .backend
+                .chat_with_history(
+                    &mut self.history,
+                    prompt.clone(),
+                    &ChatOptions::from_config(&self.config).json::<T>(),
+                )
+                .await;
+
+            let res = match res {
+                Ok(res) => res,
+                Err(e) => {
+                    self.backend_failed(&e);
+                    continue;
+                }
+            };
+
+            match serde_json::from_str(&res) {
+                Ok(t) => return Ok(Some(t)),
+                Err(e) => {
+                    println!("[DEBUG] {}: invalid response: {}", self.name, e);
+                    self.errors += 1;
+
+                    prompt = ChatMessage::system(format!(
+                        "Your response could not be understood ({e}). Please try again."
+                    ));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
     pub fn reproduce(
         &self,
-        honesty: f32,
-        socialness: f32,
-        selfishness: f32,
-        compassion: f32,
-        food_ability: f32,
-        all_names: &[String],
+        other: &Agent,
+        roster: &[String],
+        id: AgentId,
+        name: String,
+        rng: &mut impl Rng,
     ) -> Agent {
-        let my_weight = random::<f32>();
-        let other_weight = 1.0 - my_weight;
-
-        let new_honesty = honesty * other_weight + self.honesty * my_weight;
-        let new_socialness = socialness * other_weight + self.socialness * my_weight;
-        let new_selfishness = selfishness * other_weight + self.selfishness * my_weight;
-        let new_compassion = compassion * other_weight + self.compassion * my_weight;
-        let new_food_ability = food_ability * other_weight + self.food_ability * my_weight;
+        let [honesty, socialness, selfishness, compassion, food_ability] =
+            inheritance::from_config(&self.config.inheritance).inherit(
+                self.traits(),
+                other.traits(),
+                rng,
+            );
 
         let mut a = Agent {
-            ollama: self.ollama.clone(),
+            backend: self.backend.clone(),
+            config: self.config.clone(),
 
-            name: random::<FirstName>().to_string(),
-            money: 10,
+            id,
+            name,
+            parents: Some([self.id, other.id]),
+            generation: self.generation.max(other.generation) + 1,
+            last_child: None,
+            // paid for by the parents
+            money: self.config.reproduction.cost.money,
             age: 0,
-            food: 5,
+            food: self.config.reproduction.cost.food,
             history: vec![],
-            honesty: new_honesty,
-            socialness: new_socialness,
-            selfishness: new_selfishness,
-            compassion: new_compassion,
-            food_ability: new_food_ability,
+            errors: 0,
+            memories: String::new(),
+            episodes: EpisodicMemory::default(),
+            honesty,
+            socialness,
+            selfishness,
+            compassion,
+            food_ability,
         };
 
-        let mut new_names = all_names.to_vec();
+        let mut new_names = roster.to_vec();
         new_names.push(a.name.clone());
 
         a.history
@@ -249,24 +652,78 @@ You can take the following Actions. Pay attention to the arguments - they are re
         a
     }
 
-    // returns true if we are dead )':
-    pub fn age(&mut self) -> bool {
+    /// Returns why the agent died, if it did
+    pub fn age(&mut self) -> Option<DeathCause> {
         self.age += 1;
         if self.food == 0 {
-            return true;
+            return Some(DeathCause::Starvation);
         }
         self.food -= 1;
 
         // TODO
-        if self.age == 80 {
-            return true;
+        if self.age >= self.config.agent.death_age {
+            return Some(DeathCause::OldAge);
         }
 
-        false
+        None
     }
 }
 
 #[derive(JsonSchema, Deserialize, Debug)]
 struct MessageReply {
     message: String,
+    #[serde(default)]
+    end_conversation: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use rand::rngs::StdRng;
+
+    use super::*;
+
+    // answers yes to everything, remembering what it was asked with
+    #[derive(Debug, Default)]
+    struct Recorder(Mutex<Vec<ChatOptions>>);
+
+    #[async_trait]
+    impl ChatBackend for Recorder {
+        async fn chat(&self, _: &[ChatMessage], options: &ChatOptions) -> anyhow::Result<String> {
+            self.0.lock().unwrap().push(options.clone());
+            Ok("true".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn every_request_uses_the_configured_options() {
+        let mut config = Config::default();
+        config.agent.temperature = 0.25;
+        config.agent.num_ctx = 1234;
+        let backend = Arc::new(Recorder::default());
+        let names = vec!["Ann".to_string()];
+        let mut agent = Agent::new_random(
+            backend.clone(),
+            Arc::new(config),
+            &names,
+            AgentId(0),
+            "Ann".into(),
+            &mut StdRng::seed_from_u64(0),
+        );
+
+        assert_eq!(agent.vote("Yes?", "").await.unwrap(), Some(true));
+        assert!(agent.propose("baby?".into(), &"Bob".into()).await.unwrap());
+
+        let sent = backend.0.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        for options in sent.iter() {
+            assert_eq!(
+                (options.temperature, options.num_ctx),
+                (Some(0.25), Some(1234))
+            );
+            assert!(options.format.is_some());
+        }
+    }
 }
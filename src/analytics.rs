@@ -0,0 +1,129 @@
+use std::{collections::BTreeMap, fmt::Write, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::agent::{Agent, AgentId, TRAITS};
+
+/// Everyone who has ever lived, kept after they die so whole family trees
+/// and generations can be looked at afterwards
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LineageRecord {
+    pub id: AgentId,
+    pub name: String,
+    pub parents: Option<[AgentId; 2]>,
+    pub generation: u32,
+    pub born: u32,
+    // or when they were exiled
+    pub died: Option<u32>,
+    #[serde(default)]
+    pub exiled: bool,
+    // in the same order as TRAITS
+    pub traits: [f32; 5],
+}
+
+impl LineageRecord {
+    pub fn new(agent: &Agent, born: u32) -> Self {
+        Self {
+            id: agent.id,
+            name: agent.name.clone(),
+            parents: agent.parents,
+            generation: agent.generation,
+            born,
+            died: None,
+            exiled: false,
+            traits: agent.traits(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GenerationStats {
+    pub generation: u32,
+    pub count: usize,
+    pub mean: [f32; 5],
+    pub variance: [f32; 5],
+}
+
+/// Mean and (population) variance of every trait, per generation
+pub fn generation_stats(lineage: &[LineageRecord]) -> Vec<GenerationStats> {
+    let mut generations: BTreeMap<u32, Vec<&LineageRecord>> = BTreeMap::new();
+    for r in lineage {
+        generations.entry(r.generation).or_default().push(r);
+    }
+
+    generations
+        .into_iter()
+        .map(|(generation, members)| {
+            let n = members.len() as f32;
+            let mean: [f32; 5] =
+                std::array::from_fn(|t| members.iter().map(|r| r.traits[t]).sum::<f32>() / n);
+            let variance = std::array::from_fn(|t| {
+                members
+                    .iter()
+                    .map(|r| (r.traits[t] - mean[t]).powi(2))
+                    .sum::<f32>()
+                    / n
+            });
+
+            GenerationStats {
+                generation,
+                count: members.len(),
+                mean,
+                variance,
+            }
+        })
+        .collect()
+}
+
+/// One line per generation, e.g.
+/// `gen 1 (3 people): honesty 4.21 (var 1.30), ...`
+pub fn describe_generations(lineage: &[LineageRecord]) -> String {
+    let mut s = String::new();
+    for g in generation_stats(lineage) {
+        let traits: Vec<_> = TRAITS
+            .iter()
+            .enumerate()
+            .map(|(t, name)| format!("{name} {:.2} (var {:.2})", g.mean[t], g.variance[t]))
+            .collect();
+        writeln!(
+            s,
+            "gen {} ({} people): {}",
+            g.generation,
+            g.count,
+            traits.join(", ")
+        )
+        .unwrap();
+    }
+    s
+}
+
+/// Graphviz DOT of who descends from whom
+pub fn family_tree_dot(lineage: &[LineageRecord]) -> String {
+    let mut s = "digraph family_tree {\n".to_string();
+    for r in lineage {
+        let died = match r.died {
+            Some(t) if r.exiled => format!("{t} (exiled)"),
+            Some(t) => t.to_string(),
+            None => "alive".to_string(),
+        };
+        writeln!(
+            s,
+            "    {} [label=\"{}\\ngen {}, {}-{}\"];",
+            r.id, r.name, r.generation, r.born, died
+        )
+        .unwrap();
+    }
+    for r in lineage {
+        for parent in r.parents.iter().flatten() {
+            writeln!(s, "    {parent} -> {};", r.id).unwrap();
+        }
+    }
+    s.push_str("}\n");
+    s
+}
+
+pub fn export_family_tree(lineage: &[LineageRecord], path: &Path) -> anyhow::Result<()> {
+    fs::write(path, family_tree_dot(lineage))?;
+
+    Ok(())
+}
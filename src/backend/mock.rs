@@ -0,0 +1,134 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::Path,
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+use ollama_rs::generation::chat::{ChatMessage, MessageRole};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde_json::{json, Value};
+
+use super::{ChatBackend, ChatOptions};
+
+/// Offline backend for exercising the simulation without a model.
+///
+/// Queued responses are replayed in order, one per call. Calls are answered
+/// in the order they arrive, so a script only lines up with the roster when
+/// agents ask one at a time (`backend.max_in_flight = 1`). Once the queue is
+/// empty, a simple seeded policy takes over: make food when hungry, sell it
+/// when there's plenty, otherwise work, make food, broadcast or bid at
+/// random; replies are canned and proposals are a coin flip. The policy's
+/// randomness depends only on the seed and the conversation so far, so it
+/// gives the same answers however many agents are asking at once.
+#[derive(Debug)]
+pub struct MockBackend {
+    script: Mutex<VecDeque<Value>>,
+    seed: u64,
+}
+
+impl MockBackend {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            script: Mutex::new(VecDeque::new()),
+            seed,
+        }
+    }
+
+    /// Loads a script file - a JSON array of responses, e.g.
+    /// `[{"thinking": "", "action": "Work", "args": {}}, {"message": "hi"}, true]`
+    pub fn from_script(path: impl AsRef<Path>, seed: u64) -> anyhow::Result<Self> {
+        let script: Vec<Value> = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+        let backend = Self::new(seed);
+        for response in script {
+            backend.push(response);
+        }
+
+        Ok(backend)
+    }
+
+    /// Queues a response to be returned by a later call
+    pub fn push(&self, response: Value) {
+        self.script.lock().unwrap().push_back(response);
+    }
+
+    // responses still queued
+    #[cfg(test)]
+    pub fn remaining(&self) -> usize {
+        self.script.lock().unwrap().len()
+    }
+
+    fn policy(&self, messages: &[ChatMessage], options: &ChatOptions) -> Value {
+        let mut hasher = DefaultHasher::new();
+        self.seed.hash(&mut hasher);
+        for m in messages {
+            m.content.hash(&mut hasher);
+        }
+        let mut rng = StdRng::seed_from_u64(hasher.finish());
+
+        // schemars titles the schema after the type we asked for
+        let title = options
+            .schema()
+            .and_then(|s| s.get("title").and_then(|t| t.as_str()).map(str::to_string));
+
+        match title.as_deref() {
+            Some("LlmAction") => {
+                let food = messages
+                    .iter()
+                    .rev()
+                    .find(|m| m.role == MessageRole::User)
+                    .and_then(|m| m.content.split("Currently you have ").nth(1))
+                    .and_then(|s| s.split_whitespace().next())
+                    .and_then(|s| s.parse::<u32>().ok())
+                    .unwrap_or(0);
+
+                let action = if food <= 3 {
+                    "MakeFood"
+                } else if food >= 12 {
+                    "PostOffer"
+                } else {
+                    ["Work", "MakeFood", "Broadcast", "PostBid"][rng.gen_range(0..4)]
+                };
+
+                json!({
+                    "thinking": "",
+                    "action": action,
+                    "args": {
+                        "who_to_interact_with": null,
+                        "amount": if action == "PostOffer" { 2 } else { 1 },
+                        "message": "Hello everyone!",
+                        "price": rng.gen_range(1..=5),
+                        "order_id": null,
+                    },
+                })
+            }
+            Some("MessageReply") => json!({
+                "message": "Sounds good!",
+                "end_conversation": rng.gen_bool(0.3),
+            }),
+            Some("Boolean") => json!(rng.gen_bool(0.5)),
+            _ => json!("I have nothing to say."),
+        }
+    }
+}
+
+#[async_trait]
+impl ChatBackend for MockBackend {
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        options: &ChatOptions,
+    ) -> anyhow::Result<String> {
+        let response = self.script.lock().unwrap().pop_front();
+        let response = response.unwrap_or_else(|| self.policy(messages, options));
+
+        // unstructured requests get plain text back, like a real model
+        Ok(match response {
+            Value::String(s) if options.format.is_none() => s,
+            other => other.to_string(),
+        })
+    }
+}
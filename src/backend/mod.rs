@@ -0,0 +1,143 @@
+use std::{fmt::Debug, sync::Arc};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use ollama_rs::{
+    generation::{
+        chat::ChatMessage,
+        parameters::{FormatType, JsonStructure},
+    },
+    Ollama,
+};
+use schemars::JsonSchema;
+
+use crate::config::{BackendKind, Config};
+
+mod mock;
+mod ollama;
+mod openai;
+
+pub use mock::MockBackend;
+pub use ollama::OllamaBackend;
+pub use openai::OpenAiBackend;
+
+pub const DEFAULT_MODEL: &str = "llama3.2:3b";
+
+#[derive(Clone, Debug, Default)]
+pub struct ChatOptions {
+    pub format: Option<JsonStructure>,
+    pub temperature: Option<f32>,
+    pub num_ctx: Option<u32>,
+    pub seed: Option<u64>,
+}
+
+impl ChatOptions {
+    /// What every request an agent makes is sent with, so none of them falls
+    /// back to the server's defaults (e.g. a context too small for the
+    /// history)
+    pub fn from_config(config: &Config) -> Self {
+        Self::default()
+            .temperature(config.agent.temperature)
+            .num_ctx(config.agent.num_ctx)
+            .seed(config.seed())
+    }
+
+    // asks for a reply matching T's schema
+    pub fn json<T: JsonSchema>(mut self) -> Self {
+        self.format = Some(JsonStructure::new::<T>());
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn num_ctx(mut self, num_ctx: u32) -> Self {
+        self.num_ctx = Some(num_ctx);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    // the schema as plain JSON, for backends that don't speak ollama's types
+    pub fn schema(&self) -> Option<serde_json::Value> {
+        self.format
+            .clone()
+            .and_then(|s| serde_json::to_value(FormatType::StructuredJson(s)).ok())
+    }
+}
+
+pub fn from_config(config: &Config) -> anyhow::Result<Arc<dyn ChatBackend>> {
+    let mock_seed = config.backend.mock_seed.unwrap_or(config.seed());
+    let config = &config.backend;
+
+    Ok(match config.kind {
+        BackendKind::Ollama => {
+            let ollama = match &config.url {
+                Some(url) => Ollama::try_new(url.as_str())?,
+                None => Ollama::default(),
+            };
+            Arc::new(OllamaBackend::new(ollama, config.model.clone()))
+        }
+        BackendKind::OpenAi => Arc::new(OpenAiBackend::new(
+            config
+                .url
+                .clone()
+                .context("The openai backend needs a url")?,
+            config.api_key.clone(),
+            config.model.clone(),
+        )),
+        BackendKind::Mock => match &config.mock_script {
+            Some(script) => Arc::new(MockBackend::from_script(script, mock_seed)?),
+            None => Arc::new(MockBackend::new(mock_seed)),
+        },
+    })
+}
+
+// Placeholder for agents that have been loaded but not yet given a backend
+#[derive(Debug)]
+struct Detached;
+
+#[async_trait]
+impl ChatBackend for Detached {
+    async fn chat(&self, _: &[ChatMessage], _: &ChatOptions) -> anyhow::Result<String> {
+        anyhow::bail!("Agent is not attached to a backend")
+    }
+}
+
+pub fn detached() -> Arc<dyn ChatBackend> {
+    Arc::new(Detached)
+}
+
+#[async_trait]
+pub trait ChatBackend: Debug + Send + Sync {
+    /// Runs a completion over `messages` and returns the content of the reply
+    async fn chat(&self, messages: &[ChatMessage], options: &ChatOptions)
+        -> anyhow::Result<String>;
+
+    /// Pushes `message` onto `history`, then sends the whole history.
+    /// The reply is pushed onto `history` as well
+    async fn chat_with_history(
+        &self,
+        history: &mut Vec<ChatMessage>,
+        message: ChatMessage,
+        options: &ChatOptions,
+    ) -> anyhow::Result<String> {
+        history.push(message);
+
+        let reply = self.chat(history, options).await?;
+        history.push(ChatMessage::assistant(reply.clone()));
+
+        Ok(reply)
+    }
+
+    /// Total tokens generated over this backend's lifetime, for throughput
+    /// reporting. 0 if the backend doesn't know
+    fn tokens_generated(&self) -> u64 {
+        0
+    }
+}
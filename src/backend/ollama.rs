@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use ollama_rs::{
+    generation::{
+        chat::{request::ChatMessageRequest, ChatMessage},
+        options::GenerationOptions,
+        parameters::FormatType,
+    },
+    Ollama,
+};
+
+use super::{ChatBackend, ChatOptions};
+
+#[derive(Debug)]
+pub struct OllamaBackend {
+    ollama: Ollama,
+    model: String,
+    tokens: AtomicU64,
+}
+
+impl OllamaBackend {
+    pub fn new(ollama: Ollama, model: String) -> Self {
+        Self {
+            ollama,
+            model,
+            tokens: AtomicU64::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl ChatBackend for OllamaBackend {
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        options: &ChatOptions,
+    ) -> anyhow::Result<String> {
+        let mut req = ChatMessageRequest::new(self.model.clone(), messages.to_vec());
+
+        if let Some(format) = &options.format {
+            req = req.format(FormatType::StructuredJson(format.clone()));
+        }
+
+        let mut gen_options = GenerationOptions::default();
+        if let Some(temperature) = options.temperature {
+            gen_options = gen_options.temperature(temperature);
+        }
+        if let Some(num_ctx) = options.num_ctx {
+            gen_options = gen_options.num_ctx(num_ctx);
+        }
+        if let Some(seed) = options.seed {
+            // ollama only takes 32 bits
+            gen_options = gen_options.seed(seed as i32);
+        }
+
+        let res = self
+            .ollama
+            .send_chat_messages(req.options(gen_options))
+            .await
+            // OllamaError isn't Send, so it can't be wrapped directly
+            .map_err(|e| anyhow!("{e}"))?;
+
+        if let Some(data) = &res.final_data {
+            self.tokens
+                .fetch_add(data.eval_count as u64, Ordering::Relaxed);
+        }
+
+        Ok(res.message.content)
+    }
+
+    fn tokens_generated(&self) -> u64 {
+        self.tokens.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use ollama_rs::generation::chat::{ChatMessage, MessageRole};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{ChatBackend, ChatOptions};
+
+/// Talks to anything exposing an OpenAI-compatible `/v1/chat/completions`
+/// endpoint - llama.cpp server, vLLM, LM Studio, etc.
+#[derive(Debug)]
+pub struct OpenAiBackend {
+    client: reqwest::Client,
+    // e.g. http://localhost:8080/v1
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    tokens: AtomicU64,
+}
+
+impl OpenAiBackend {
+    pub fn new(base_url: String, api_key: Option<String>, model: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+            tokens: AtomicU64::new(0),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Message<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    model: &'a str,
+    messages: Vec<Message<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    choices: Vec<Choice>,
+    // not every server reports this
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize)]
+struct Usage {
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ResponseMessage,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    content: Option<String>,
+}
+
+#[async_trait]
+impl ChatBackend for OpenAiBackend {
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        options: &ChatOptions,
+    ) -> anyhow::Result<String> {
+        let messages = messages
+            .iter()
+            .map(|m| Message {
+                role: match m.role {
+                    MessageRole::User => "user",
+                    MessageRole::Assistant => "assistant",
+                    MessageRole::System => "system",
+                    MessageRole::Tool => "tool",
+                },
+                content: &m.content,
+            })
+            .collect();
+
+        // num_ctx has no equivalent here - context size is a server-side setting
+        let req = Request {
+            model: &self.model,
+            messages,
+            temperature: options.temperature,
+            seed: options.seed,
+            // not strict: strict mode wants every property required and no
+            // additional properties, which our optional arguments don't meet,
+            // so servers enforcing it would reject the schema outright
+            response_format: options.schema().map(|schema| {
+                json!({
+                    "type": "json_schema",
+                    "json_schema": {
+                        "name": "response",
+                        "schema": schema,
+                    },
+                })
+            }),
+        };
+
+        let mut builder = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&req);
+        if let Some(key) = &self.api_key {
+            builder = builder.bearer_auth(key);
+        }
+
+        let res = builder.send().await?.error_for_status()?;
+        let res: Response = res.json().await?;
+
+        if let Some(usage) = &res.usage {
+            self.tokens
+                .fetch_add(usage.completion_tokens, Ordering::Relaxed);
+        }
+
+        res.choices
+            .into_iter()
+            .next()
+            .and_then(|c| c.message.content)
+            .context("Backend returned no message")
+    }
+
+    fn tokens_generated(&self) -> u64 {
+        self.tokens.load(Ordering::Relaxed)
+    }
+}
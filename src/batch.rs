@@ -0,0 +1,246 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    agent::{Agent, TRAITS},
+    backend,
+    config::{BatchArgs, Config},
+    environment::Environment,
+    event::{self, DeathCause, Event, EventSink, JsonlSink},
+    ledger::Resource,
+};
+
+/// What one simulation in a batch came to
+#[derive(Debug)]
+struct RunSummary {
+    seed: u64,
+    // steps survived, or the step limit
+    steps: u32,
+    extinct: bool,
+    peak_population: usize,
+    births: u32,
+    starvation_deaths: u32,
+    old_age_deaths: u32,
+    // averaged over every step anyone was alive
+    gini_money: f64,
+    gini_food: f64,
+    // how far each trait's mean moved from the founders' to that of
+    // everyone alive at the last populated step, founders included
+    drift: [f32; 5],
+}
+
+#[derive(Default)]
+struct Counts {
+    births: u32,
+    starvation_deaths: u32,
+    old_age_deaths: u32,
+}
+
+// Tallies births and deaths on the way through to the real sink
+struct CountingSink {
+    inner: Box<dyn EventSink>,
+    counts: Arc<Mutex<Counts>>,
+}
+
+impl EventSink for CountingSink {
+    fn emit(&mut self, time: u32, event: &Event) -> anyhow::Result<()> {
+        {
+            let mut counts = self.counts.lock().unwrap();
+            match event {
+                Event::Birth { .. } => counts.births += 1,
+                Event::Death {
+                    cause: DeathCause::Starvation,
+                    ..
+                } => counts.starvation_deaths += 1,
+                Event::Death {
+                    cause: DeathCause::OldAge,
+                    ..
+                } => counts.old_age_deaths += 1,
+                _ => {}
+            }
+        }
+
+        self.inner.emit(time, event)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Runs `args.runs` simulations of `config`, seeded one after another from
+/// its seed, and writes a CSV with one row per run
+pub async fn run(config: Config, args: &BatchArgs) -> anyhow::Result<()> {
+    let base_seed = config.seed();
+    let limit = Arc::new(Semaphore::new(args.parallel.max(1)));
+
+    let mut tasks = JoinSet::new();
+    for k in 0..args.runs {
+        let config = run_config(&config, base_seed.wrapping_add(k as u64));
+        let limit = limit.clone();
+        let max_steps = args.max_steps;
+
+        tasks.spawn(async move {
+            let _permit = limit.acquire_owned().await;
+            simulate(config, max_steps).await
+        });
+    }
+
+    let mut summaries = Vec::with_capacity(args.runs as usize);
+    while let Some(res) = tasks.join_next().await {
+        let summary = res??;
+        println!(
+            "[INFO] Run with seed {} finished after {} steps",
+            summary.seed, summary.steps
+        );
+        summaries.push(summary);
+    }
+    summaries.sort_by_key(|s| s.seed);
+
+    fs::write(&args.out, to_csv(&summaries))?;
+    println!(
+        "[INFO] Wrote {} runs to {}",
+        summaries.len(),
+        args.out.display()
+    );
+
+    Ok(())
+}
+
+// Each run gets its own seed and its own output files, and never snapshots
+fn run_config(config: &Config, seed: u64) -> Arc<Config> {
+    let mut config = config.clone();
+    config.seed = Some(seed);
+    config.snapshot.every = 0;
+
+    let stem = config
+        .events
+        .path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    config.events.path = config
+        .events
+        .path
+        .with_file_name(format!("{stem}-{seed}.jsonl"));
+    if let Some(dir) = &config.relationships.export_dir {
+        config.relationships.export_dir = Some(dir.join(format!("run-{seed}")));
+    }
+
+    Arc::new(config)
+}
+
+async fn simulate(config: Arc<Config>, max_steps: u32) -> anyhow::Result<RunSummary> {
+    let backend = backend::from_config(&config)?;
+    let mut env = Environment::create(backend, config.clone());
+
+    let counts = Arc::new(Mutex::new(Counts::default()));
+    let inner = match config.events.enabled {
+        true => Box::new(JsonlSink::open(&config.events.path, false)?),
+        false => event::null_sink(),
+    };
+    env.set_sink(Box::new(CountingSink {
+        inner,
+        counts: counts.clone(),
+    }));
+
+    let founders = mean_traits(&env.agents);
+    let mut last_traits = founders;
+    let mut peak_population = env.agents.len();
+    let (mut gini_money, mut gini_food, mut populated_steps) = (0.0, 0.0, 0);
+
+    while env.time() < max_steps && !env.agents.is_empty() {
+        env.run_timestep().await?;
+
+        peak_population = peak_population.max(env.agents.len());
+        if !env.agents.is_empty() {
+            gini_money += gini(&env.agents, Resource::Money);
+            gini_food += gini(&env.agents, Resource::Food);
+            populated_steps += 1;
+            last_traits = mean_traits(&env.agents);
+        }
+    }
+
+    let counts = counts.lock().unwrap();
+    let populated_steps = populated_steps.max(1) as f64;
+
+    Ok(RunSummary {
+        seed: config.seed(),
+        steps: env.time(),
+        extinct: env.agents.is_empty(),
+        peak_population,
+        births: counts.births,
+        starvation_deaths: counts.starvation_deaths,
+        old_age_deaths: counts.old_age_deaths,
+        gini_money: gini_money / populated_steps,
+        gini_food: gini_food / populated_steps,
+        drift: std::array::from_fn(|t| (last_traits[t] - founders[t]).abs()),
+    })
+}
+
+fn mean_traits(agents: &[Agent]) -> [f32; 5] {
+    let mut sums = [0.0; 5];
+    for a in agents {
+        for (sum, t) in sums.iter_mut().zip(a.traits()) {
+            *sum += t;
+        }
+    }
+    sums.map(|s| s / agents.len().max(1) as f32)
+}
+
+// 0 when everyone has the same, approaching 1 when one person has it all
+fn gini(agents: &[Agent], resource: Resource) -> f64 {
+    let mut values: Vec<_> = agents.iter().map(|a| a.balance(resource) as f64).collect();
+    values.sort_by(f64::total_cmp);
+
+    let n = values.len() as f64;
+    let total: f64 = values.iter().sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+
+    let weighted: f64 = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (2.0 * (i as f64 + 1.0) - n - 1.0) * v)
+        .sum();
+    weighted / (n * total)
+}
+
+fn to_csv(summaries: &[RunSummary]) -> String {
+    let mut csv = "seed,steps,extinct,peak_population,births,starvation_deaths,old_age_deaths,gini_money,gini_food,trait_drift".to_string();
+    for t in TRAITS {
+        write!(csv, ",drift_{t}").unwrap();
+    }
+    csv.push('\n');
+
+    for s in summaries {
+        let mean_drift = s.drift.iter().sum::<f32>() / s.drift.len() as f32;
+        write!(
+            csv,
+            "{},{},{},{},{},{},{},{:.4},{:.4},{:.4}",
+            s.seed,
+            s.steps,
+            s.extinct,
+            s.peak_population,
+            s.births,
+            s.starvation_deaths,
+            s.old_age_deaths,
+            s.gini_money,
+            s.gini_food,
+            mean_drift
+        )
+        .unwrap();
+        for d in s.drift {
+            write!(csv, ",{d:.4}").unwrap();
+        }
+        csv.push('\n');
+    }
+
+    csv
+}
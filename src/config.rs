@@ -0,0 +1,689 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::bail;
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use rand::random;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::{Action, RetryPolicy},
+    backend::DEFAULT_MODEL,
+    ledger::Goods,
+    relationships::GraphFormat,
+};
+
+/// Everything about a scenario that can be changed without recompiling.
+/// Loaded from a TOML or JSON file, then overridden by the command line
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // picked at random by `load` if not given
+    pub seed: Option<u64>,
+    pub population: usize,
+    pub backend: BackendConfig,
+    pub agent: AgentConfig,
+    pub retry: RetryPolicy,
+    pub market: MarketConfig,
+    pub contracts: ContractConfig,
+    pub snapshot: SnapshotConfig,
+    pub events: EventConfig,
+    pub memory: MemoryConfig,
+    pub episodes: EpisodeConfig,
+    pub relationships: RelationshipConfig,
+    pub lineage: LineageConfig,
+    pub inheritance: InheritanceConfig,
+    pub reproduction: ReproductionConfig,
+    pub conversation: ConversationConfig,
+    pub meetings: MeetingConfig,
+    pub governance: GovernanceConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            population: 4,
+            backend: BackendConfig::default(),
+            agent: AgentConfig::default(),
+            retry: RetryPolicy::default(),
+            market: MarketConfig::default(),
+            contracts: ContractConfig::default(),
+            snapshot: SnapshotConfig::default(),
+            events: EventConfig::default(),
+            memory: MemoryConfig::default(),
+            episodes: EpisodeConfig::default(),
+            relationships: RelationshipConfig::default(),
+            lineage: LineageConfig::default(),
+            inheritance: InheritanceConfig::default(),
+            reproduction: ReproductionConfig::default(),
+            conversation: ConversationConfig::default(),
+            meetings: MeetingConfig::default(),
+            governance: GovernanceConfig::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Ollama,
+    // anything speaking OpenAI's /v1/chat/completions
+    #[value(name = "openai")]
+    OpenAi,
+    Mock,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackendConfig {
+    pub kind: BackendKind,
+    pub model: String,
+    // ollama defaults to localhost; required for openai
+    pub url: Option<String>,
+    pub api_key: Option<String>,
+    pub mock_script: Option<PathBuf>,
+    // defaults to the simulation's seed
+    pub mock_seed: Option<u64>,
+    // agents deciding at once; match the server's parallelism
+    pub max_in_flight: usize,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        Self {
+            kind: BackendKind::Ollama,
+            model: DEFAULT_MODEL.to_string(),
+            url: None,
+            api_key: None,
+            mock_script: None,
+            mock_seed: None,
+            max_in_flight: 4,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentConfig {
+    pub starting_money: u32,
+    pub starting_food: u32,
+    // anything past this is thrown away
+    pub max_food: u32,
+    pub death_age: u32,
+    pub temperature: f32,
+    pub num_ctx: u32,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            starting_money: 10,
+            starting_food: 5,
+            max_food: 20,
+            death_age: 80,
+            temperature: 0.9,
+            num_ctx: 16_384,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarketConfig {
+    // orders left unfilled this long are cancelled and refunded
+    pub order_lifetime: u32,
+}
+
+impl Default for MarketConfig {
+    fn default() -> Self {
+        Self { order_lifetime: 10 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContractConfig {
+    // turn off to see how a purely trust-based economy fares
+    pub enabled: bool,
+}
+
+impl Default for ContractConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    pub path: PathBuf,
+    // steps between snapshots; 0 turns autosaving off entirely
+    pub every: u32,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("snapshot.json"),
+            every: 10,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventConfig {
+    pub enabled: bool,
+    // JSONL file, appended to when resuming
+    pub path: PathBuf,
+}
+
+impl Default for EventConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: PathBuf::from("events.jsonl"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemoryConfig {
+    // recent messages kept word for word when compacting
+    pub window: usize,
+    // steps between compactions; 0 never compacts
+    pub interval: u32,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            window: 40,
+            interval: 10,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct EpisodeConfig {
+    // most episodes an agent keeps; 0 remembers nothing
+    pub capacity: usize,
+    // how many are recalled into each step prompt
+    pub top_k: usize,
+}
+
+impl Default for EpisodeConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 200,
+            top_k: 5,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelationshipConfig {
+    // a graph file is written here every step; none if unset
+    pub export_dir: Option<PathBuf>,
+    pub format: GraphFormat,
+}
+
+impl Default for RelationshipConfig {
+    fn default() -> Self {
+        Self {
+            export_dir: None,
+            format: GraphFormat::Dot,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LineageConfig {
+    // DOT file of everyone who ever lived, written on exit; none if unset
+    pub family_tree: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InheritanceKind {
+    // one random mix of the parents for every trait
+    Blend,
+    // a separate random mix for each trait
+    #[value(name = "per_trait")]
+    PerTrait,
+    // each trait taken whole from one parent, extremes dominant
+    Dominant,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct InheritanceConfig {
+    pub kind: InheritanceKind,
+    // standard deviation of the noise added to every inherited trait; 0 for none
+    pub mutation_sigma: f32,
+    // chance the dominant trait wins, for the dominant model
+    pub dominance: f32,
+}
+
+impl Default for InheritanceConfig {
+    fn default() -> Self {
+        Self {
+            kind: InheritanceKind::Blend,
+            mutation_sigma: 0.0,
+            dominance: 0.75,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReproductionConfig {
+    // paid by the parents between them, and what the child starts with
+    pub cost: Goods,
+    pub min_age: u32,
+    pub max_age: u32,
+    // steps after having a child before someone can have another
+    pub cooldown: u32,
+    // children can't Work or MakeFood until this age
+    pub childhood: u32,
+}
+
+impl Default for ReproductionConfig {
+    fn default() -> Self {
+        Self {
+            cost: Goods { food: 5, money: 10 },
+            min_age: 10,
+            max_age: 60,
+            cooldown: 10,
+            childhood: 10,
+        }
+    }
+}
+
+impl ReproductionConfig {
+    /// What each parent pays. The proposer covers any odd unit
+    pub fn shares(&self) -> (Goods, Goods) {
+        let partner = Goods {
+            food: self.cost.food / 2,
+            money: self.cost.money / 2,
+        };
+        let proposer = Goods {
+            food: self.cost.food - partner.food,
+            money: self.cost.money - partner.money,
+        };
+        (proposer, partner)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConversationConfig {
+    // lines said in a conversation, counting the opening one
+    pub max_turns: u32,
+}
+
+impl Default for ConversationConfig {
+    fn default() -> Self {
+        Self { max_turns: 6 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MeetingConfig {
+    // times everyone gets to speak, after the chair opens
+    pub rounds: u32,
+}
+
+impl Default for MeetingConfig {
+    fn default() -> Self {
+        Self { rounds: 2 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct GovernanceConfig {
+    // whether agents can propose and vote on rules
+    pub enabled: bool,
+}
+
+impl Default for GovernanceConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(about = "A community of LLM-driven people trying to survive")]
+pub struct Args {
+    /// Scenario file (.toml or .json)
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Seed for everything random in the simulation, including the model
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    #[arg(long)]
+    pub population: Option<usize>,
+
+    #[arg(long, value_enum)]
+    pub backend: Option<BackendKind>,
+    #[arg(long)]
+    pub model: Option<String>,
+    /// Backend URL, e.g. http://localhost:8080/v1 for an OpenAI-compatible server
+    #[arg(long)]
+    pub url: Option<String>,
+    #[arg(long, env = "OPENAI_API_KEY", hide_env_values = true)]
+    pub api_key: Option<String>,
+    /// JSON array of canned responses for the mock backend. Needs
+    /// --max-in-flight 1
+    #[arg(long)]
+    pub mock_script: Option<PathBuf>,
+    #[arg(long)]
+    pub mock_seed: Option<u64>,
+    /// How many agents may wait on the backend at once
+    #[arg(long)]
+    pub max_in_flight: Option<usize>,
+
+    #[arg(long)]
+    pub starting_money: Option<u32>,
+    #[arg(long)]
+    pub starting_food: Option<u32>,
+    #[arg(long)]
+    pub max_food: Option<u32>,
+    #[arg(long)]
+    pub death_age: Option<u32>,
+    #[arg(long)]
+    pub temperature: Option<f32>,
+    #[arg(long)]
+    pub num_ctx: Option<u32>,
+
+    #[arg(long)]
+    pub max_retries: Option<u32>,
+    #[arg(long)]
+    pub order_lifetime: Option<u32>,
+    #[arg(long)]
+    pub contracts: Option<bool>,
+
+    /// Where to save snapshots of the simulation
+    #[arg(long)]
+    pub snapshot: Option<PathBuf>,
+    /// Steps between snapshots (0 to disable)
+    #[arg(long)]
+    pub snapshot_every: Option<u32>,
+    /// Recent messages kept verbatim when an agent's history is summarized
+    #[arg(long)]
+    pub memory_window: Option<usize>,
+    /// Steps between history summarizations (0 to disable)
+    #[arg(long)]
+    pub memory_interval: Option<u32>,
+
+    /// Most long-term memories each agent keeps
+    #[arg(long)]
+    pub episode_capacity: Option<usize>,
+    /// Long-term memories recalled into each decision
+    #[arg(long)]
+    pub recall_top_k: Option<usize>,
+
+    /// Directory to write the relationship graph to every step
+    #[arg(long)]
+    pub graph_dir: Option<PathBuf>,
+    #[arg(long, value_enum)]
+    pub graph_format: Option<GraphFormat>,
+
+    /// How children inherit their parents' traits
+    #[arg(long, value_enum)]
+    pub inheritance: Option<InheritanceKind>,
+    /// Standard deviation of mutations to inherited traits (0 to disable)
+    #[arg(long)]
+    pub mutation_sigma: Option<f32>,
+    #[arg(long)]
+    pub dominance: Option<f32>,
+
+    /// Food it costs two parents to have a child, which the child starts with
+    #[arg(long)]
+    pub birth_food: Option<u32>,
+    /// Money it costs two parents to have a child, which the child starts with
+    #[arg(long)]
+    pub birth_money: Option<u32>,
+    #[arg(long)]
+    pub min_fertile_age: Option<u32>,
+    #[arg(long)]
+    pub max_fertile_age: Option<u32>,
+    /// Steps between someone's children
+    #[arg(long)]
+    pub reproduction_cooldown: Option<u32>,
+
+    /// Steps before a child can Work or MakeFood
+    #[arg(long)]
+    pub childhood: Option<u32>,
+
+    /// Most lines said in one conversation, counting the opening one
+    #[arg(long)]
+    pub max_turns: Option<u32>,
+    /// Times everyone speaks at a meeting
+    #[arg(long)]
+    pub meeting_rounds: Option<u32>,
+    /// Whether agents can propose and vote on community rules
+    #[arg(long)]
+    pub governance: Option<bool>,
+
+    /// Write a family tree (Graphviz DOT) here on exit
+    #[arg(long)]
+    pub family_tree: Option<PathBuf>,
+
+    /// Where to write the event log
+    #[arg(long)]
+    pub events: Option<PathBuf>,
+    /// Whether to write the event log at all
+    #[arg(long)]
+    pub log_events: Option<bool>,
+    /// Continue a simulation from a snapshot instead of starting a new one.
+    /// Its saved config is used, with any other flags applied on top
+    #[arg(long, conflicts_with = "config")]
+    pub resume: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run many seeded simulations without interaction and write a summary CSV.
+    /// Flags before `batch` set up the scenario, as for a single run
+    Batch(BatchArgs),
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct BatchArgs {
+    /// Number of simulations. Run k uses seed + k
+    #[arg(long, default_value_t = 10)]
+    pub runs: u32,
+    /// Stop a simulation after this many steps even if anyone is still alive
+    #[arg(long, default_value_t = 1000)]
+    pub max_steps: u32,
+    /// How many simulations to run at once
+    #[arg(long, default_value_t = 1)]
+    pub parallel: usize,
+    /// Where to write the summary
+    #[arg(long, default_value = "batch.csv")]
+    pub out: PathBuf,
+}
+
+impl Config {
+    pub fn load(args: &Args) -> anyhow::Result<Self> {
+        let mut config = match &args.config {
+            Some(path) => {
+                let s = fs::read_to_string(path)?;
+                match path.extension().and_then(|e| e.to_str()) {
+                    Some("toml") => toml::from_str(&s)?,
+                    Some("json") => serde_json::from_str(&s)?,
+                    _ => bail!("Config file must be .toml or .json"),
+                }
+            }
+            None => Config::default(),
+        };
+
+        config.apply(args);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Rejects settings that can't work together, rather than letting the
+    /// simulation quietly do something else
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let max_food = self.agent.max_food;
+        if self.agent.starting_food > max_food {
+            bail!(
+                "agent.starting_food ({}) can't be more than agent.max_food ({max_food})",
+                self.agent.starting_food
+            );
+        }
+        if self.reproduction.cost.food > max_food {
+            bail!(
+                "reproduction.cost.food ({}) can't be more than agent.max_food ({max_food}), since the baby starts out with it",
+                self.reproduction.cost.food
+            );
+        }
+        if self.reproduction.min_age > self.reproduction.max_age {
+            bail!(
+                "reproduction.min_age ({}) can't be more than reproduction.max_age ({})",
+                self.reproduction.min_age,
+                self.reproduction.max_age
+            );
+        }
+        if self.backend.kind == BackendKind::Mock
+            && self.backend.mock_script.is_some()
+            && self.backend.max_in_flight != 1
+        {
+            bail!("a mock script is replayed in the order requests arrive, so it needs backend.max_in_flight = 1");
+        }
+        if !matches!(
+            self.retry.fallback,
+            Action::Idle | Action::Work | Action::MakeFood
+        ) {
+            bail!(
+                "retry.fallback must be Idle, Work or MakeFood, not {:?}",
+                self.retry.fallback
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Overrides whatever was given on the command line
+    pub fn apply(&mut self, args: &Args) {
+        let config = self;
+
+        fn set<T: Clone>(field: &mut T, value: &Option<T>) {
+            if let Some(v) = value {
+                *field = v.clone();
+            }
+        }
+
+        if args.seed.is_some() {
+            config.seed = args.seed;
+        }
+        config.seed.get_or_insert_with(random);
+
+        set(&mut config.population, &args.population);
+
+        set(&mut config.backend.kind, &args.backend);
+        set(&mut config.backend.model, &args.model);
+        set(&mut config.backend.max_in_flight, &args.max_in_flight);
+        if args.mock_seed.is_some() {
+            config.backend.mock_seed = args.mock_seed;
+        }
+        if args.url.is_some() {
+            config.backend.url = args.url.clone();
+        }
+        if args.api_key.is_some() {
+            config.backend.api_key = args.api_key.clone();
+        }
+        if args.mock_script.is_some() {
+            config.backend.mock_script = args.mock_script.clone();
+        }
+
+        set(&mut config.agent.starting_money, &args.starting_money);
+        set(&mut config.agent.starting_food, &args.starting_food);
+        set(&mut config.agent.max_food, &args.max_food);
+        set(&mut config.agent.death_age, &args.death_age);
+        set(&mut config.agent.temperature, &args.temperature);
+        set(&mut config.agent.num_ctx, &args.num_ctx);
+
+        set(&mut config.retry.max_retries, &args.max_retries);
+        set(&mut config.market.order_lifetime, &args.order_lifetime);
+        set(&mut config.contracts.enabled, &args.contracts);
+        set(&mut config.snapshot.path, &args.snapshot);
+        set(&mut config.snapshot.every, &args.snapshot_every);
+        set(&mut config.memory.window, &args.memory_window);
+        set(&mut config.memory.interval, &args.memory_interval);
+        set(&mut config.episodes.capacity, &args.episode_capacity);
+        set(&mut config.episodes.top_k, &args.recall_top_k);
+        if args.graph_dir.is_some() {
+            config.relationships.export_dir = args.graph_dir.clone();
+        }
+        set(&mut config.relationships.format, &args.graph_format);
+        set(&mut config.inheritance.kind, &args.inheritance);
+        set(&mut config.inheritance.mutation_sigma, &args.mutation_sigma);
+        set(&mut config.inheritance.dominance, &args.dominance);
+        set(&mut config.reproduction.cost.food, &args.birth_food);
+        set(&mut config.reproduction.cost.money, &args.birth_money);
+        set(&mut config.reproduction.min_age, &args.min_fertile_age);
+        set(&mut config.reproduction.max_age, &args.max_fertile_age);
+        set(
+            &mut config.reproduction.cooldown,
+            &args.reproduction_cooldown,
+        );
+        set(&mut config.reproduction.childhood, &args.childhood);
+        set(&mut config.conversation.max_turns, &args.max_turns);
+        set(&mut config.meetings.rounds, &args.meeting_rounds);
+        set(&mut config.governance.enabled, &args.governance);
+        if args.family_tree.is_some() {
+            config.lineage.family_tree = args.family_tree.clone();
+        }
+        set(&mut config.events.path, &args.events);
+        set(&mut config.events.enabled, &args.log_events);
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_valid() {
+        Config::default().validate().unwrap();
+    }
+
+    #[test]
+    fn settings_that_cant_work_together_are_rejected() {
+        let broken: [fn(&mut Config); 5] = [
+            |c| c.agent.starting_food = c.agent.max_food + 1,
+            |c| c.reproduction.cost.food = c.agent.max_food + 1,
+            |c| c.reproduction.min_age = c.reproduction.max_age + 1,
+            |c| c.retry.fallback = Action::GiveFood,
+            |c| {
+                c.backend.kind = BackendKind::Mock;
+                c.backend.mock_script = Some("script.json".into());
+            },
+        ];
+
+        for breakage in broken {
+            let mut config = Config::default();
+            breakage(&mut config);
+            assert!(config.validate().is_err(), "{config:?}");
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{agent::AgentId, ledger::Goods};
+
+/// A two-sided trade. The proposer's side is held in escrow from the moment
+/// it is proposed, so the swap either happens in full or not at all
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Contract {
+    pub id: u32,
+    pub proposer: AgentId,
+    pub counterparty: AgentId,
+    // what the proposer hands over
+    pub give: Goods,
+    // what the proposer gets back
+    pub receive: Goods,
+    pub proposed: u32,
+    // last step at which it can be accepted
+    pub deadline: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContractOutcome {
+    Executed,
+    Rejected,
+    Expired,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ContractBook {
+    next_id: u32,
+    pub open: Vec<Contract>,
+    pub closed: Vec<(Contract, ContractOutcome)>,
+}
+
+impl ContractBook {
+    pub fn propose(
+        &mut self,
+        proposer: AgentId,
+        counterparty: AgentId,
+        give: Goods,
+        receive: Goods,
+        time: u32,
+        deadline: u32,
+    ) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.open.push(Contract {
+            id,
+            proposer,
+            counterparty,
+            give,
+            receive,
+            proposed: time,
+            deadline: time.saturating_add(deadline),
+        });
+
+        id
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Contract> {
+        self.open.iter().find(|c| c.id == id)
+    }
+
+    pub fn close(&mut self, id: u32, outcome: ContractOutcome) -> Option<Contract> {
+        let pos = self.open.iter().position(|c| c.id == id)?;
+        let contract = self.open.remove(pos);
+        self.closed.push((contract.clone(), outcome));
+
+        Some(contract)
+    }
+
+    /// Ids of contracts past their deadline or involving someone who is gone
+    pub fn stale(&self, time: u32, is_alive: impl Fn(AgentId) -> bool) -> Vec<u32> {
+        self.open
+            .iter()
+            .filter(|c| time > c.deadline || !is_alive(c.proposer) || !is_alive(c.counterparty))
+            .map(|c| c.id)
+            .collect()
+    }
+}
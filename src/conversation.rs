@@ -0,0 +1,70 @@
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::agent::Agent;
+
+/// Something said in a conversation
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Line {
+    pub speaker: String,
+    pub message: String,
+}
+
+/// Why a conversation stopped
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ending {
+    // someone said goodbye
+    Goodbye,
+    TurnLimit,
+    // someone's reply couldn't be understood
+    NoReply,
+}
+
+/// Goes back and forth between `agents[0]`, who opened with `opening`, and
+/// `agents[1]` until either ends it or `max_turns` lines have been said
+pub async fn run(
+    agents: [&mut Agent; 2],
+    opening: String,
+    max_turns: u32,
+) -> anyhow::Result<(Vec<Line>, Ending)> {
+    let names = [agents[0].name.clone(), agents[1].name.clone()];
+    println!("[DEBUG] {} -> {}: {}", names[0], names[1], opening);
+    let mut lines = vec![Line {
+        speaker: names[0].clone(),
+        message: opening,
+    }];
+
+    for turn in 1..max_turns as usize {
+        let (speaker, listener) = (turn % 2, (turn + 1) % 2);
+        let Some((message, goodbye)) = agents[speaker]
+            .converse(&names[listener], &transcript(&lines))
+            .await?
+        else {
+            return Ok((lines, Ending::NoReply));
+        };
+
+        println!(
+            "[DEBUG] {} -> {}: {}",
+            names[speaker], names[listener], message
+        );
+        lines.push(Line {
+            speaker: names[speaker].clone(),
+            message,
+        });
+        if goodbye {
+            return Ok((lines, Ending::Goodbye));
+        }
+    }
+
+    Ok((lines, Ending::TurnLimit))
+}
+
+/// One `Name: message` line per thing said
+pub fn transcript(lines: &[Line]) -> String {
+    let mut s = String::new();
+    for line in lines {
+        writeln!(s, "{}: {}", line.speaker, line.message).unwrap();
+    }
+    s
+}
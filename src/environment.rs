@@ -1,218 +1,2219 @@
-use crate::{action::Action, agent::Agent};
+use std::{collections::HashMap, sync::Arc, time::Instant};
+
+use crate::{
+    action::{Decision, ValidAction},
+    agent::{Agent, AgentId},
+    analytics::LineageRecord,
+    backend::{self, ChatBackend},
+    config::Config,
+    contract::{ContractBook, ContractOutcome},
+    conversation::{self, Ending},
+    episodes,
+    event::{self, DeathCause, Event, EventSink},
+    governance::{Governance, Rule},
+    ledger::{Goods, Resource, Transfer, RESOURCES},
+    market::{Fill, Market, Order, Side},
+    meeting, names,
+    relationships::RelationshipGraph,
+};
 use faker_rand::en_us::names::FirstName;
-use ollama_rs::{generation::chat::ChatMessage, Ollama};
-use rand::random;
+use ollama_rs::generation::chat::ChatMessage;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+// stands in for a recipient in the ledger when goods go to the community
+pub const COMMUNITY_POOL: &str = "the community pool";
 
+#[derive(Serialize, Deserialize)]
 pub struct Environment {
     time: u32,
-    all_names: Vec<String>,
     pub agents: Vec<Agent>,
+    pub config: Arc<Config>,
+    // shared by every agent; reattached on resume
+    #[serde(skip, default = "backend::detached")]
+    backend: Arc<dyn ChatBackend>,
+    // every gift/trade that has gone through
+    pub ledger: Vec<Transfer>,
+    pub market: Market,
+    pub contracts: ContractBook,
+    #[serde(default)]
+    pub relationships: RelationshipGraph,
+    // everyone who has ever lived, in order of birth
+    pub lineage: Vec<LineageRecord>,
+    // estates nobody inherited, and food taxes
+    #[serde(default)]
+    pub pool: Goods,
+    #[serde(default)]
+    pub governance: Governance,
+    next_id: AgentId,
+    // all randomness in the simulation comes from here
+    rng: ChaCha12Rng,
+    // error counts of agents who have died or been exiled
+    retired_errors: Vec<(String, u32)>,
+    // where this run's events are written; reattached on resume
+    #[serde(skip, default = "event::null_sink")]
+    sink: Box<dyn EventSink>,
+    // events from the step in progress, handed to the sink when it ends
+    #[serde(skip)]
+    pending: Vec<Event>,
 }
 
 impl Environment {
-    pub fn create(ollama: Ollama, num_agents: usize) -> Self {
+    pub fn create(backend: Arc<dyn ChatBackend>, config: Arc<Config>) -> Self {
+        let num_agents = config.population;
         let mut new_env = Environment {
             time: 0,
-            all_names: Vec::new(),
             agents: Vec::with_capacity(num_agents),
+            config: config.clone(),
+            backend: backend.clone(),
+            ledger: Vec::new(),
+            market: Market::default(),
+            contracts: ContractBook::default(),
+            relationships: RelationshipGraph::default(),
+            lineage: Vec::new(),
+            pool: Goods::default(),
+            governance: Governance::default(),
+            next_id: AgentId(0),
+            rng: ChaCha12Rng::seed_from_u64(config.seed()),
+            retired_errors: Vec::new(),
+            sink: event::null_sink(),
+            pending: Vec::new(),
         };
 
         let mut all_names: Vec<_> = Vec::new();
 
         for _ in 0..num_agents {
-            let name = random::<FirstName>().to_string();
+            let name = new_env.rng.gen::<FirstName>().to_string();
+            let name = names::unique(name, |n| all_names.iter().any(|a| a == n));
             all_names.push(name);
         }
 
         for i in 0..num_agents {
             let name = all_names[i].clone();
-            new_env
-                .agents
-                .push(Agent::new_random(ollama.clone(), &all_names, name));
+            let id = new_env.new_id();
+            let agent = Agent::new_random(
+                backend.clone(),
+                config.clone(),
+                &all_names,
+                id,
+                name,
+                &mut new_env.rng,
+            );
+            new_env.lineage.push(LineageRecord::new(&agent, 0));
+            new_env.agents.push(agent);
         }
-        new_env.all_names = all_names;
         new_env
     }
 
+    /// Hands every agent the backend and this environment's config. Needed
+    /// after loading a snapshot, since neither is saved
+    pub fn attach(&mut self, backend: Arc<dyn ChatBackend>, config: Arc<Config>) {
+        for agent in self.agents.iter_mut() {
+            agent.backend = backend.clone();
+            agent.config = config.clone();
+        }
+        self.backend = backend;
+        self.config = config;
+    }
+
+    pub fn set_sink(&mut self, sink: Box<dyn EventSink>) {
+        self.sink = sink;
+    }
+
+    fn emit(&mut self, event: Event) {
+        self.pending.push(event);
+    }
+
+    pub fn time(&self) -> u32 {
+        self.time
+    }
+
+    /// Runs one step in phases: everyone decides, then actions are resolved
+    /// in the order agents were listed, then children conceived this step are
+    /// born, then everyone who acted ages (and maybe dies), then the dead are
+    /// removed and the market and contracts are settled. Newborns first act
+    /// and age next step
     pub async fn run_timestep(&mut self) -> anyhow::Result<()> {
+        let started = Instant::now();
+
         println!(
             "\n\n[INFO] There are {} people in the community",
             self.agents.len()
         );
 
+        self.emit(Event::StepStarted {
+            population: self.agents.len(),
+        });
+
+        // vote on rules proposed last step
+        self.hold_votes().await?;
+
+        // decide
+        let acting: Vec<AgentId> = self.agents.iter().map(|a| a.id).collect();
+        let roster = self.roster();
+        let departed = self.departed();
+
+        let market = self.market.describe(|id| self.name_of(id));
+
+        let tokens_before = self.backend.tokens_generated();
+        let compact = self.config.memory.interval > 0
+            && self.time > 0
+            && self.time.is_multiple_of(self.config.memory.interval);
+        let rules = format!(
+            "{}The community pool holds {}, to be shared out equally at the end of this step.\n",
+            self.governance.describe(),
+            self.pool
+        );
+        let decisions = self
+            .decide(roster, departed, rules, market, compact)
+            .await?;
+        let decide_secs = started.elapsed().as_secs_f64();
+        let tokens = self.backend.tokens_generated() - tokens_before;
+
         let mut actions = Vec::with_capacity(self.agents.len());
-        for agent in self.agents.iter_mut() {
-            actions.push(agent.step().await?);
+        for (i, decision) in decisions.into_iter().enumerate() {
+            let agent = self.agents[i].name.clone();
+            for error in decision.errors {
+                self.emit(Event::InvalidAction {
+                    agent: agent.clone(),
+                    error,
+                });
+            }
+            self.emit(Event::ActionTaken {
+                agent,
+                thinking: decision.thinking,
+                action: decision.action.clone().named(|id| self.name_of(id)),
+            });
+
+            actions.push(decision.action);
         }
 
+        // resolve interactions
+        let mut conceived = vec![];
+        for (id, action) in acting.iter().zip(actions) {
+            let i = self.index_of(*id).unwrap();
+            self.resolve_action(i, action, &mut conceived).await?;
+        }
+
+        // births
+        for parents in conceived {
+            self.give_birth(parents).await;
+        }
+
+        // aging and deaths
         let mut dead = vec![];
+        for id in &acting {
+            let i = self.index_of(*id).unwrap();
+            if let Some(cause) = self.agents[i].age() {
+                self.die(i, cause, &dead).await;
+                dead.push(*id);
+            }
+        }
 
-        for (i, action) in actions.into_iter().enumerate() {
-            println!();
-            println!(
-                "[DEBUG] {}: (thinking) {}",
-                self.agents[i].name, action.thinking
-            );
-            println!(
-                "[DEBUG] {}: took action {:?} with params {:?}",
-                self.agents[i].name, action.action, action.args
-            );
-            println!(
-                "[DEBUG] {}: {} food, {} money, {} age",
-                self.agents[i].name, self.agents[i].food, self.agents[i].money, self.agents[i].age
-            );
+        // cleanup
+        for id in dead {
+            let i = self.index_of(id).unwrap();
+            let gone = self.agents.remove(i);
+            self.relationships.forget(gone.id);
+        }
 
-            match action.action {
-                Action::Work => {
-                    self.agents[i].work();
-                }
-                Action::MakeFood => {
-                    self.agents[i].make_food();
+        self.clear_market();
+        self.expire_contracts();
+        self.share_pool();
+
+        if let Some(dir) = &self.config.relationships.export_dir {
+            self.relationships.export(
+                &self.roster(),
+                dir,
+                self.time,
+                self.config.relationships.format,
+            )?;
+        }
+
+        let step_secs = started.elapsed().as_secs_f64();
+        println!(
+            "[INFO] Step {} took {step_secs:.2}s, {decide_secs:.2}s of it deciding ({tokens} tokens, {:.1} tokens/s)",
+            self.time,
+            tokens as f64 / decide_secs
+        );
+        self.emit(Event::StepFinished {
+            step_secs,
+            decide_secs,
+            tokens,
+        });
+
+        for event in std::mem::take(&mut self.pending) {
+            self.sink.emit(self.time, &event)?;
+        }
+        self.sink.flush()?;
+
+        self.time += 1;
+        Ok(())
+    }
+
+    // Carries out agent `i`'s action. Children agreed to are added to
+    // `conceived` to be born once every action is done
+    async fn resolve_action(
+        &mut self,
+        i: usize,
+        action: ValidAction,
+        conceived: &mut Vec<[AgentId; 2]>,
+    ) -> anyhow::Result<()> {
+        println!();
+        println!("[DEBUG] {}: doing {:?}", self.agents[i].name, action);
+        println!(
+            "[DEBUG] {}: {} food, {} money, {} age",
+            self.agents[i].name, self.agents[i].food, self.agents[i].money, self.agents[i].age
+        );
+
+        match action {
+            ValidAction::Work => {
+                self.agents[i].work();
+            }
+            ValidAction::MakeFood => {
+                let before = self.agents[i].food;
+                self.agents[i].make_food();
+                // nothing was made if we were already over the cap
+                self.tax_food(i, self.agents[i].food.saturating_sub(before));
+            }
+            ValidAction::GiveMoney { to, amount } => {
+                if let Some(other_id) = self.index_of(to) {
+                    self.transfer(i, other_id, Resource::Money, amount);
+                } else {
+                    self.agents[i].history.push(
+                        ChatMessage::system("You tried to interact with someone who is not in the community! Please interact with members of the community".to_string())
+                        );
                 }
-                Action::GiveMoney => {
-                    if let Some(other_id) =
-                        self.get_id_from_name(&action.args.who_to_interact_with.unwrap())
-                    {
-                        let name = self.agents[i].name.clone();
-                        self.agents[other_id].give_money(action.args.amount.unwrap(), &name);
-                    } else {
-                        self.agents[i].history.push(
-                            ChatMessage::system("You tried to interact with someone who is not in the community! Please interact with members of the community".to_string())
-                            );
-                    }
+            }
+            ValidAction::GiveFood { to, amount } => {
+                if let Some(other_id) = self.index_of(to) {
+                    self.transfer(i, other_id, Resource::Food, amount);
+                } else {
+                    self.agents[i].history.push(
+                        ChatMessage::system("You tried to interact with someone who is not in the community! Please interact with members of the community".to_string()
+                        ));
                 }
-                Action::GiveFood => {
-                    if let Some(other_id) =
-                        self.get_id_from_name(&action.args.who_to_interact_with.unwrap())
-                    {
-                        let name = self.agents[i].name.clone();
-                        self.agents[other_id].give_food(action.args.amount.unwrap(), &name);
-                    } else {
-                        self.agents[i].history.push(
-                            ChatMessage::system("You tried to interact with someone who is not in the community! Please interact with members of the community".to_string()
-                            ));
+            }
+            ValidAction::Converse { to, message } => {
+                if let Some(other_id) = self.index_of(to) {
+                    let from = self.agents[i].id;
+                    let name = self.agents[i].name.clone();
+                    let to_name = self.agents[other_id].name.clone();
+                    let [me, other] = self.agents.get_disjoint_mut([i, other_id]).unwrap();
+                    let (lines, ending) =
+                        conversation::run([me, other], message, self.config.conversation.max_turns)
+                            .await?;
+
+                    // everyone warms a little to whoever talks to them
+                    let mut importance: f32 = 0.0;
+                    for line in &lines {
+                        let (speaker, listener) = if line.speaker == name {
+                            (from, to)
+                        } else {
+                            (to, from)
+                        };
+                        self.relationships.adjust(listener, speaker, 0.0, 0.05);
+                        importance = importance.max(episodes::message_importance(&line.message));
                     }
-                }
-                Action::Converse => {
-                    if let Some(other_id) =
-                        self.get_id_from_name(&action.args.who_to_interact_with.unwrap())
-                    {
-                        let name = self.agents[i].name.clone();
-                        let msg_back = self.agents[other_id]
-                            .send_msg(action.args.message.unwrap(), &name)
-                            .await;
-                        self.agents[i].listen(msg_back, &name).await;
-                    } else {
-                        self.agents[i].history.push(
-                            ChatMessage::system("You tried to interact with someone who is not in the community! Please interact with members of the community".to_string()
-                            ));
+
+                    let transcript = conversation::transcript(&lines);
+                    let ended = match ending {
+                        Ending::Goodbye => "",
+                        Ending::TurnLimit => "You ran out of time to talk.\n",
+                        Ending::NoReply => "The conversation trailed off.\n",
+                    };
+                    for (j, partner) in [(i, &to_name), (other_id, &name)] {
+                        self.agents[j].history.push(ChatMessage::user(format!(
+                            "Your conversation with {partner}:\n{transcript}{ended}"
+                        )));
+                        self.remember(
+                            j,
+                            format!("You talked with {partner}:\n{transcript}"),
+                            importance,
+                        );
                     }
+
+                    self.emit(Event::Conversation {
+                        participants: [name, to_name],
+                        lines,
+                        ending,
+                    });
+                } else {
+                    self.agents[i].history.push(
+                        ChatMessage::system("You tried to interact with someone who is not in the community! Please interact with members of the community".to_string()
+                        ));
                 }
-                Action::Reproduce => {
-                    if let Some(index) =
-                        self.get_id_from_name(&action.args.who_to_interact_with.unwrap())
-                    {
-                        let name = self.agents[i].name.clone();
-                        let accepted = self.agents[index]
-                            .propose(action.args.message.unwrap(), &name)
-                            .await?;
-                        if accepted {
-                            let honesty = self.agents[index].honesty;
-                            let socialness = self.agents[index].socialness;
-                            let selfishness = self.agents[index].selfishness;
-                            let compassion = self.agents[index].compassion;
-                            let food_ability = self.agents[index].food_ability;
-                            let new_agent = self.agents[i].reproduce(
-                                honesty,
-                                socialness,
-                                selfishness,
-                                compassion,
-                                food_ability,
-                                &self.all_names,
-                            );
-
-                            println!("[DEBUG] New person: {}", new_agent.name);
-
-                            let mut new_names = self.all_names.to_vec();
-                            new_names.push(new_agent.name.clone());
-
-                            for j in 0..self.agents.len() {
-                                if i == j {
-                                    continue;
-                                }
-                                self.agents[j]
-                                    .listen(
-                                        format!(
-                                            "There's a new member of the community named {}!",
-                                            new_agent.name.clone()
-                                        ),
-                                        &name,
-                                    )
-                                    .await;
-
-                                self.agents[j]
-                                    .listen(
-                                        format!(
-                                            "There's a new member of the community named {}!",
-                                            new_agent.name.clone()
-                                        ),
-                                        &name,
-                                    )
-                                    .await;
+            }
+            ValidAction::Reproduce { to, message } => {
+                let target = self.index_of(to);
+                let problem = target.and_then(|index| self.reproduction_problem(i, index));
+                if let Some(problem) = problem {
+                    println!("[DEBUG] {}: {problem}", self.agents[i].name);
+                    self.agents[i].history.push(ChatMessage::system(problem));
+                } else if let Some(index) = target {
+                    let me = self.agents[i].id;
+                    let name = self.agents[i].name.clone();
+                    let to_name = self.agents[index].name.clone();
+                    let accepted = self.agents[index].propose(message, &name).await?;
+                    self.emit(Event::ReproductionProposed {
+                        from: name.clone(),
+                        to: to_name.clone(),
+                        accepted,
+                    });
+                    if accepted {
+                        // paid now, so it can't be spent before the birth
+                        let (proposer_share, partner_share) = self.config.reproduction.shares();
+                        for (parent, share) in [(i, proposer_share), (index, partner_share)] {
+                            self.agents[parent].last_child = Some(self.time);
+                            for r in RESOURCES {
+                                self.agents[parent].debit(r, share.get(r));
                             }
-
-                            self.agents.push(new_agent);
-                            self.all_names = new_names;
+                            self.agents[parent]
+                                .history
+                                .push(ChatMessage::system(format!(
+                                    "You put {share} towards your new baby, who will be born at the end of this step."
+                                )));
                         }
+
+                        self.relationships.adjust(me, to, 0.1, 0.3);
+                        self.relationships.adjust(to, me, 0.1, 0.3);
+                        conceived.push([me, to]);
                     } else {
-                        self.agents[i].history.push(
-                                ChatMessage::system("You tried to interact with someone who is not in the community! Please interact with members of the community".to_string()
-                                ));
+                        self.relationships.adjust(me, to, 0.0, -0.1);
+                        self.remember(
+                            i,
+                            format!("{to_name} turned down having a baby with you"),
+                            5.0,
+                        );
                     }
+                } else {
+                    self.agents[i].history.push(
+                            ChatMessage::system("You tried to interact with someone who is not in the community! Please interact with members of the community".to_string()
+                            ));
                 }
+            }
 
-                Action::Broadcast => {
-                    let name = self.agents[i].name.clone();
-                    for j in 0..self.agents.len() {
-                        if i == j {
-                            continue;
-                        }
-                        self.agents[j]
-                            .listen(action.args.message.clone().unwrap(), &name)
-                            .await;
+            ValidAction::Broadcast { message } => {
+                let name = self.agents[i].name.clone();
+                self.emit(Event::Broadcast {
+                    from: name.clone(),
+                    message: message.clone(),
+                });
+                for j in 0..self.agents.len() {
+                    if i == j {
+                        continue;
                     }
+                    self.agents[j].listen(message.clone(), &name).await;
                 }
             }
+            ValidAction::CallMeeting {
+                with,
+                agenda,
+                question,
+            } => {
+                let chair = self.agents[i].id;
+                let name = self.agents[i].name.clone();
+                let invited: Vec<AgentId> = match with {
+                    Some(with) => with
+                        .into_iter()
+                        .filter(|id| self.index_of(*id).is_some())
+                        .collect(),
+                    None => self.agents.iter().map(|a| a.id).collect(),
+                };
+                if invited.iter().all(|id| *id == chair) {
+                    self.agents[i].history.push(ChatMessage::system(
+                        "Nobody else could come to your meeting.".to_string(),
+                    ));
+                    return Ok(());
+                }
+
+                // the chair speaks first, then everyone else in community order
+                let mut attendees: Vec<&mut Agent> = self
+                    .agents
+                    .iter_mut()
+                    .filter(|a| a.id == chair || invited.contains(&a.id))
+                    .collect();
+                let c = attendees.iter().position(|a| a.id == chair).unwrap();
+                let chair_agent = attendees.remove(c);
+                attendees.insert(0, chair_agent);
+                let (ids, participants): (Vec<_>, Vec<_>) =
+                    attendees.iter().map(|a| (a.id, a.name.clone())).unzip();
+
+                let (lines, vote) =
+                    meeting::run(attendees, &agenda, self.config.meetings.rounds, question).await?;
 
-            if self.agents[i].age() {
+                let transcript = conversation::transcript(&lines);
+                let outcome = vote.as_ref().map(|v| v.describe()).unwrap_or_default();
+                for id in ids {
+                    let j = self.index_of(id).unwrap();
+                    self.agents[j].history.push(ChatMessage::user(format!(
+                        "The meeting {name} called about '{agenda}':\n{transcript}{outcome}"
+                    )));
+                    self.remember(
+                        j,
+                        format!("At {name}'s meeting about '{agenda}': {outcome}"),
+                        6.0,
+                    );
+                }
+
+                self.emit(Event::Meeting {
+                    chair: name,
+                    participants,
+                    lines,
+                    vote,
+                });
+            }
+            ValidAction::PostOffer { amount, price } => {
+                self.post_order(i, Side::Offer, amount, price);
+            }
+            ValidAction::PostBid { amount, price } => {
+                self.post_order(i, Side::Bid, amount, price);
+            }
+            ValidAction::AcceptOffer { order_id, amount } => {
+                self.accept_offer(i, order_id, amount);
+            }
+            ValidAction::ProposeContract { .. } if !self.config.contracts.enabled => {
+                self.agents[i].history.push(ChatMessage::system(
+                    "Contracts are not available in this community.".to_string(),
+                ));
+            }
+            ValidAction::ProposeContract {
+                to,
+                give,
+                receive,
+                deadline,
+            } => {
+                if let Some(other_id) = self.index_of(to) {
+                    self.propose_contract(i, other_id, give, receive, deadline);
+                } else {
+                    self.agents[i].history.push(
+                        ChatMessage::system("You tried to interact with someone who is not in the community! Please interact with members of the community".to_string()
+                        ));
+                }
+            }
+            ValidAction::AcceptContract { contract_id } => {
+                self.accept_contract(i, contract_id);
+            }
+            ValidAction::RejectContract { contract_id } => match self.contracts.get(contract_id) {
+                Some(c) if c.counterparty == self.agents[i].id => {
+                    self.refund_contract(contract_id, ContractOutcome::Rejected);
+                }
+                _ => self.agents[i].history.push(ChatMessage::system(format!(
+                    "There is no contract #{contract_id} waiting for you."
+                ))),
+            },
+            ValidAction::ProposeRule { .. } if !self.config.governance.enabled => {
+                self.agents[i].history.push(ChatMessage::system(
+                    "This community doesn't make rules.".to_string(),
+                ));
+            }
+            ValidAction::ProposeRule { rule, message } => {
                 let name = self.agents[i].name.clone();
+                let id =
+                    self.governance
+                        .propose(name.clone(), rule.clone(), message.clone(), self.time);
+                println!("[DEBUG] {name} proposed rule #{id}: {rule}");
+                self.emit(Event::RuleProposed {
+                    id,
+                    proposer: name,
+                    rule,
+                    message,
+                });
+                self.agents[i].history.push(ChatMessage::system(format!(
+                    "Everyone will vote on your proposed rule #{id} next step."
+                )));
+            }
+            ValidAction::Idle => {}
+        }
 
-                println!("[DEBUG] {name} has died");
+        Ok(())
+    }
 
-                for j in 0..self.agents.len() {
-                    if i == j {
-                        continue;
+    // Adds a child of `parents` to the community. Its starting goods were
+    // already taken from the parents when they agreed
+    async fn give_birth(&mut self, parents: [AgentId; 2]) {
+        let [i, index] = parents.map(|id| self.index_of(id).unwrap());
+        let name = self.agents[i].name.clone();
+        let to = self.agents[index].name.clone();
+
+        let id = self.new_id();
+        let child_name = self.new_name();
+        let roster: Vec<_> = self.agents.iter().map(|a| a.name.clone()).collect();
+        let new_agent =
+            self.agents[i].reproduce(&self.agents[index], &roster, id, child_name, &mut self.rng);
+        self.lineage.push(LineageRecord::new(&new_agent, self.time));
+
+        let (proposer_share, partner_share) = self.config.reproduction.shares();
+        for (parent, share) in [(&name, proposer_share), (&to, partner_share)] {
+            for r in RESOURCES {
+                self.record(parent.clone(), new_agent.name.clone(), r, share.get(r));
+            }
+        }
+
+        println!("[DEBUG] New person: {}", new_agent.name);
+        self.emit(Event::Birth {
+            name: new_agent.name.clone(),
+            parents: [name.clone(), to.clone()],
+        });
+        for parent in parents {
+            self.relationships.adjust(new_agent.id, parent, 0.5, 0.5);
+            self.relationships.adjust(parent, new_agent.id, 0.0, 0.5);
+        }
+        for j in 0..self.agents.len() {
+            let text = if j == i {
+                format!("You had a baby with {to}, named {}", new_agent.name)
+            } else if j == index {
+                format!("You had a baby with {name}, named {}", new_agent.name)
+            } else {
+                format!("{} was born to {name} and {to}", new_agent.name)
+            };
+            let importance = if j == i || j == index { 9.0 } else { 3.0 };
+            self.remember(j, text, importance);
+        }
+
+        for j in 0..self.agents.len() {
+            if i == j {
+                continue;
+            }
+            self.agents[j]
+                .listen(
+                    format!(
+                        "There's a new member of the community named {}!",
+                        new_agent.name
+                    ),
+                    &name,
+                )
+                .await;
+        }
+
+        self.agents.push(new_agent);
+    }
+
+    // Tells everyone agent `i` has died and hands out their estate. `dead`
+    // are those who died before them this step
+    async fn die(&mut self, i: usize, cause: DeathCause, dead: &[AgentId]) {
+        let name = self.agents[i].name.clone();
+
+        println!("[DEBUG] {name} has died");
+        self.emit(Event::Death {
+            name: name.clone(),
+            age: self.agents[i].age,
+            cause,
+        });
+        for j in 0..self.agents.len() {
+            if j != i {
+                self.remember(j, format!("{name} died ({cause:?})"), 4.0);
+            }
+        }
+
+        for j in 0..self.agents.len() {
+            if i == j {
+                continue;
+            }
+            self.agents[j]
+                .listen(format!("{} has died. Rest in peace.", &name), &name)
+                .await;
+        }
+        self.settle_estate(i, dead);
+
+        self.retired_errors.push((name, self.agents[i].errors));
+        let id = self.agents[i].id;
+        if let Some(r) = self.lineage.iter_mut().find(|r| r.id == id) {
+            r.died = Some(self.time);
+        }
+    }
+
+    // Every agent decides at once, up to max_in_flight waiting on the backend.
+    // Decisions come back in agent order, so resolving them stays reproducible.
+    // If `compact` is set, each agent summarizes its history first
+    async fn decide(
+        &mut self,
+        roster: Vec<(AgentId, String)>,
+        departed: Vec<String>,
+        rules: String,
+        market: String,
+        compact: bool,
+    ) -> anyhow::Result<Vec<Decision>> {
+        let limit = Arc::new(Semaphore::new(self.config.backend.max_in_flight.max(1)));
+        let roster = Arc::new(roster);
+        let departed = Arc::new(departed);
+        let rules = Arc::new(rules);
+        let market = Arc::new(market);
+
+        let time = self.time;
+        let mut tasks = JoinSet::new();
+        for (i, mut agent) in std::mem::take(&mut self.agents).into_iter().enumerate() {
+            let relationships = self.relationships.describe(agent.id, &roster);
+            let roster = roster.clone();
+            let departed = departed.clone();
+            let rules = rules.clone();
+            let market = market.clone();
+
+            // taken here rather than in the task, so requests start in roster
+            // order
+            let permit = limit.clone().acquire_owned().await;
+            tasks.spawn(async move {
+                let _permit = permit;
+                let decision = async {
+                    if compact {
+                        agent.compact().await?;
                     }
-                    self.agents[j]
-                        .listen(format!("{} has died. Rest in peace.", &name), &name)
-                        .await;
+                    agent
+                        .step(&roster, &departed, &rules, &market, &relationships, time)
+                        .await
                 }
-                dead.push(i);
+                .await;
+                (i, agent, decision)
+            });
+        }
+
+        let mut results = Vec::with_capacity(tasks.len());
+        while let Some(res) = tasks.join_next().await {
+            match res {
+                Ok(res) => results.push(res),
+                Err(e) => std::panic::resume_unwind(e.into_panic()),
             }
         }
+        results.sort_by_key(|(i, _, _)| *i);
 
-        for d in dead.into_iter().rev() {
-            self.agents.remove(d);
+        // put everyone back before bailing, so a failed step can still be saved
+        let mut decisions = Vec::with_capacity(results.len());
+        for (_, agent, decision) in results {
+            self.agents.push(agent);
+            decisions.push(decision);
         }
-        self.time += 1;
+
+        decisions.into_iter().collect()
+    }
+
+    /// Moves goods from one agent to another, never creating or destroying any.
+    /// Returns false (and tells the giver why) if the transfer didn't happen
+    pub fn transfer(&mut self, from: usize, to: usize, resource: Resource, amount: u32) -> bool {
+        let balance = self.agents[from].balance(resource);
+        if balance < amount {
+            self.agents[from].history.push(ChatMessage::system(format!(
+                "You tried to give {} but you only have {}. Nothing was given.",
+                resource.amount(amount),
+                resource.amount(balance)
+            )));
+            return false;
+        }
+
+        // don't let food vanish into someone's full pantry
+        let amount = match resource {
+            Resource::Food => amount.min(
+                self.config
+                    .agent
+                    .max_food
+                    .saturating_sub(self.agents[to].food),
+            ),
+            Resource::Money => amount,
+        };
+        if amount == 0 {
+            let to_name = self.agents[to].name.clone();
+            self.agents[from].history.push(ChatMessage::system(format!(
+                "{to_name} can't hold any more food. Nothing was given."
+            )));
+            return false;
+        }
+
+        let from_name = self.agents[from].name.clone();
+        let to_name = self.agents[to].name.clone();
+
+        self.agents[from].debit(resource, amount);
+        self.agents[to].give(resource, amount, &from_name);
+        self.agents[from].history.push(ChatMessage::system(format!(
+            "You gave {} to {}",
+            resource.amount(amount),
+            to_name
+        )));
+
+        self.relationships
+            .adjust(self.agents[to].id, self.agents[from].id, 0.1, 0.15);
+        self.remember(
+            to,
+            format!("{from_name} gave you {}", resource.amount(amount)),
+            6.0,
+        );
+        self.remember(
+            from,
+            format!("You gave {to_name} {}", resource.amount(amount)),
+            4.0,
+        );
+
+        self.record(from_name, to_name, resource, amount);
+
+        true
+    }
+
+    // Everyone votes on each rule proposed before this step. Rules that pass
+    // take effect straight away
+    async fn hold_votes(&mut self) -> anyhow::Result<()> {
+        for proposal in self.governance.take_due(self.time) {
+            if let Rule::Exile { who, .. } = proposal.rule {
+                if self.index_of(who).is_none() {
+                    continue;
+                }
+            }
+
+            let context = format!(
+                "{}{} proposed rule #{} and said '{}'\n",
+                self.governance.describe(),
+                proposal.proposer,
+                proposal.id,
+                proposal.message
+            );
+            let question = format!("rule #{}: {}", proposal.id, proposal.rule);
+            let vote = meeting::poll(self.agents.iter_mut(), question, &context).await?;
+
+            let outcome = vote.describe();
+            println!("[DEBUG] {outcome}");
+            for j in 0..self.agents.len() {
+                self.agents[j]
+                    .history
+                    .push(ChatMessage::system(outcome.clone()));
+                self.remember(j, outcome.clone(), 6.0);
+            }
+            self.emit(Event::RuleVoted {
+                id: proposal.id,
+                rule: proposal.rule.clone(),
+                vote: vote.clone(),
+            });
+
+            if vote.passed {
+                if let Rule::Exile { who, .. } = proposal.rule {
+                    self.exile(self.index_of(who).unwrap());
+                }
+                self.governance.enact(proposal, self.time);
+            }
+        }
+
         Ok(())
     }
 
-    fn get_id_from_name(&self, name: &str) -> Option<usize> {
-        self.agents
+    // Makes agent `i` leave the community. Everything they own, including
+    // anything tied up in orders or contracts, is forfeit to the pool
+    fn exile(&mut self, i: usize) {
+        let name = self.agents[i].name.clone();
+        let held = self.withdraw_holdings(self.agents[i].id);
+        for r in RESOURCES {
+            let balance = self.agents[i].balance(r);
+            self.agents[i].debit(r, balance);
+            let forfeit = balance + held.get(r);
+            self.pool.add(r, forfeit);
+            self.record(name.clone(), COMMUNITY_POOL.to_string(), r, forfeit);
+        }
+
+        let gone = self.agents.remove(i);
+        println!("[DEBUG] {} has been exiled", gone.name);
+        self.emit(Event::Exiled {
+            name: gone.name.clone(),
+        });
+
+        for j in 0..self.agents.len() {
+            self.agents[j].history.push(ChatMessage::system(format!(
+                "{} has been exiled from the community.",
+                gone.name
+            )));
+        }
+        self.relationships.forget(gone.id);
+        if let Some(r) = self.lineage.iter_mut().find(|r| r.id == gone.id) {
+            r.died = Some(self.time);
+            r.exiled = true;
+        }
+        self.retired_errors.push((gone.name, gone.errors));
+    }
+
+    // Shares the community pool out equally among everyone. Whatever doesn't
+    // divide evenly, or won't fit in someone's pantry, waits for next step
+    fn share_pool(&mut self) {
+        let n = self.agents.len() as u32;
+        if n == 0 {
+            return;
+        }
+
+        let mut shares = vec![Goods::default(); self.agents.len()];
+        for r in RESOURCES {
+            let share = self.pool.get(r) / n;
+            if share == 0 {
+                continue;
+            }
+
+            for (j, got) in shares.iter_mut().enumerate() {
+                let amount = match r {
+                    Resource::Food => share.min(self.room(j)),
+                    Resource::Money => share,
+                };
+                self.pool.remove(r, amount);
+                self.agents[j].credit(r, amount);
+                got.add(r, amount);
+            }
+        }
+
+        for (j, got) in shares.into_iter().enumerate() {
+            if got.is_empty() {
+                continue;
+            }
+
+            let name = self.agents[j].name.clone();
+            self.agents[j].history.push(ChatMessage::system(format!(
+                "You got {got} as your share of the community pool."
+            )));
+            for r in RESOURCES {
+                self.record(COMMUNITY_POOL.to_string(), name.clone(), r, got.get(r));
+            }
+        }
+    }
+
+    // Takes the community's food tax out of the `made` food agent `i` just made
+    fn tax_food(&mut self, i: usize, made: u32) {
+        let tax = made * self.governance.food_tax() / 100;
+        if tax == 0 {
+            return;
+        }
+
+        self.agents[i].debit(Resource::Food, tax);
+        self.pool.food += tax;
+        self.agents[i].history.push(ChatMessage::system(format!(
+            "{tax} of the {made} food you made went to the community pool as tax."
+        )));
+        let name = self.agents[i].name.clone();
+        self.record(name, COMMUNITY_POOL.to_string(), Resource::Food, tax);
+    }
+
+    // Splits what agent `i` left behind between their living children, or
+    // gives it to the community pool if there are none. Food that won't fit
+    // in a child's pantry goes to the pool too
+    fn settle_estate(&mut self, i: usize, dead: &[AgentId]) {
+        let name = self.agents[i].name.clone();
+        let id = self.agents[i].id;
+        let children: Vec<_> = (0..self.agents.len())
+            .filter(|j| *j != i && !dead.contains(&self.agents[*j].id))
+            .filter(|j| self.agents[*j].parents.is_some_and(|p| p.contains(&id)))
+            .collect();
+
+        // whatever they had tied up counts too
+        let held = self.withdraw_holdings(id);
+
+        let mut to_pool = Goods::default();
+        for r in RESOURCES {
+            let balance = self.agents[i].balance(r);
+            self.agents[i].debit(r, balance);
+            let estate = balance + held.get(r);
+
+            if children.is_empty() {
+                match r {
+                    Resource::Food => to_pool.food += estate,
+                    Resource::Money => to_pool.money += estate,
+                }
+                continue;
+            }
+
+            let n = children.len() as u32;
+            for (k, &c) in children.iter().enumerate() {
+                // the eldest gets any remainder
+                let mut amount = estate / n + if k == 0 { estate % n } else { 0 };
+                if r == Resource::Food {
+                    let room = self
+                        .config
+                        .agent
+                        .max_food
+                        .saturating_sub(self.agents[c].food);
+                    to_pool.food += amount.saturating_sub(room);
+                    amount = amount.min(room);
+                }
+                if amount == 0 {
+                    continue;
+                }
+
+                self.agents[c].credit(r, amount);
+                self.agents[c].history.push(ChatMessage::system(format!(
+                    "Your parent {name} has died and left you {}.",
+                    r.amount(amount)
+                )));
+                let child = self.agents[c].name.clone();
+                self.remember(c, format!("{name} left you {}", r.amount(amount)), 7.0);
+                self.record(name.clone(), child, r, amount);
+            }
+        }
+
+        if !to_pool.is_empty() {
+            println!("[DEBUG] {name}'s estate of {to_pool} went to the community pool");
+            self.pool.food += to_pool.food;
+            self.pool.money += to_pool.money;
+            for r in RESOURCES {
+                self.record(name.clone(), COMMUNITY_POOL.to_string(), r, to_pool.get(r));
+            }
+        }
+    }
+
+    // Why `i` can't have a child with `other` under the reproduction rules,
+    // worded for `i`
+    fn reproduction_problem(&self, i: usize, other: usize) -> Option<String> {
+        let rules = &self.config.reproduction;
+        let (proposer_share, partner_share) = rules.shares();
+
+        for (j, share) in [(i, proposer_share), (other, partner_share)] {
+            let agent = &self.agents[j];
+            let reason = if agent.age < rules.min_age {
+                format!("too young (babies are allowed from age {})", rules.min_age)
+            } else if agent.age > rules.max_age {
+                format!("too old (babies are allowed until age {})", rules.max_age)
+            } else if let Some(last) = agent
+                .last_child
+                .filter(|last| self.time < last.saturating_add(rules.cooldown))
+            {
+                format!(
+                    "still recovering from the last baby (possible again from step {})",
+                    last.saturating_add(rules.cooldown)
+                )
+            } else if !self.has_goods(j, share) {
+                format!("short of the {share} needed for the baby")
+            } else {
+                continue;
+            };
+
+            let who = if j == i {
+                "you are".to_string()
+            } else {
+                format!("{} is", agent.name)
+            };
+            return Some(format!(
+                "You can't have a baby with {} right now: {who} {reason}, so you didn't ask.",
+                self.agents[other].name
+            ));
+        }
+
+        None
+    }
+
+    fn new_id(&mut self) -> AgentId {
+        let id = self.next_id;
+        self.next_id.0 += 1;
+        id
+    }
+
+    // A random first name nobody has had before, suffixed if need be, so
+    // nobody is mistaken for someone else, living or dead
+    fn new_name(&mut self) -> String {
+        let name = self.rng.gen::<FirstName>().to_string();
+        names::unique(name, |n| self.lineage.iter().any(|r| r.name == n))
+    }
+
+    /// Works out which living agent `name` means, forgiving case, spacing and
+    /// small typos. The error says why nobody matched, for showing to whoever
+    /// asked
+    pub fn resolve(&self, name: &str) -> Result<AgentId, String> {
+        let names: Vec<_> = self.agents.iter().map(|a| a.name.clone()).collect();
+        let i = names::resolve(name, &names, &self.departed())?;
+
+        Ok(self.agents[i].id)
+    }
+
+    // Everyone living, for working out who agents mean
+    fn roster(&self) -> Vec<(AgentId, String)> {
+        self.agents.iter().map(|a| (a.id, a.name.clone())).collect()
+    }
+
+    // What to call someone in prompts and events. Everyone who has ever
+    // lived is in the lineage, so this works for the departed too
+    fn name_of(&self, id: AgentId) -> String {
+        self.lineage
             .iter()
-            .enumerate()
-            .find(|(_, a)| a.name == name)
-            .map(|(id, _)| id)
+            .find(|r| r.id == id)
+            .map(|r| r.name.clone())
+            .unwrap()
+    }
+
+    // Everyone who has died or been exiled
+    fn departed(&self) -> Vec<String> {
+        self.lineage
+            .iter()
+            .filter(|r| r.died.is_some())
+            .map(|r| r.name.clone())
+            .collect()
+    }
+
+    pub fn index_of(&self, id: AgentId) -> Option<usize> {
+        self.agents.iter().position(|a| a.id == id)
+    }
+
+    // Gives agent `i` a long-term memory of something from this step
+    fn remember(&mut self, i: usize, text: String, importance: f32) {
+        let time = self.time;
+        self.agents[i].remember(time, text, importance);
+    }
+
+    fn post_order(&mut self, i: usize, side: Side, amount: u32, price: u32) {
+        let resource = side.escrowed();
+        let Some(escrow) = Order::escrow_for(side, amount, price) else {
+            self.agents[i].history.push(ChatMessage::system(format!(
+                "Buying {amount} food at ${price} each costs more money than exists. No order was posted."
+            )));
+            return;
+        };
+
+        if self.market.crosses_own(self.agents[i].id, side, price) {
+            self.agents[i].history.push(ChatMessage::system(
+                "That order would trade with one of your own orders. No order was posted."
+                    .to_string(),
+            ));
+            return;
+        }
+
+        let balance = self.agents[i].balance(resource);
+        if balance < escrow {
+            self.agents[i].history.push(ChatMessage::system(format!(
+                "That order needs {} but you only have {}. No order was posted.",
+                resource.amount(escrow),
+                resource.amount(balance)
+            )));
+            return;
+        }
+
+        self.agents[i].debit(resource, escrow);
+        let id = self
+            .market
+            .post(side, self.agents[i].id, amount, price, self.time);
+        self.emit(Event::OrderPosted {
+            id,
+            owner: self.agents[i].name.clone(),
+            side,
+            amount,
+            price,
+        });
+
+        self.agents[i].history.push(ChatMessage::system(format!(
+            "You posted order #{id}. The market is holding {} for it.",
+            resource.amount(escrow)
+        )));
+    }
+
+    fn accept_offer(&mut self, i: usize, order_id: u32, amount: Option<u32>) {
+        let offer = match self.market.get(order_id) {
+            Some(o) if o.side == Side::Offer => o.clone(),
+            _ => {
+                self.agents[i].history.push(ChatMessage::system(format!(
+                    "There is no open offer #{order_id} on the market."
+                )));
+                return;
+            }
+        };
+
+        if offer.owner == self.agents[i].id {
+            self.agents[i].history.push(ChatMessage::system(
+                "You can't accept your own offer.".to_string(),
+            ));
+            return;
+        }
+
+        let room = self.room(i);
+        if room == 0 {
+            self.agents[i].history.push(ChatMessage::system(
+                "You can't hold any more food. Nothing was bought.".to_string(),
+            ));
+            return;
+        }
+        let amount = amount.unwrap_or(offer.amount).min(offer.amount).min(room);
+        let Some(cost) = amount.checked_mul(offer.price) else {
+            self.agents[i].history.push(ChatMessage::system(format!(
+                "Buying {amount} food from offer #{order_id} costs more money than exists."
+            )));
+            return;
+        };
+        let money = self.agents[i].money;
+        if money < cost {
+            self.agents[i].history.push(ChatMessage::system(format!(
+                "Buying {amount} food from offer #{order_id} costs ${cost} but you only have ${money}."
+            )));
+            return;
+        }
+
+        self.agents[i].debit(Resource::Money, cost);
+        self.market.take(order_id, amount);
+
+        self.settle(Fill {
+            seller: offer.owner,
+            buyer: self.agents[i].id,
+            amount,
+            price: offer.price,
+            bid_price: offer.price,
+        });
+    }
+
+    // Hands escrowed goods from a matched trade to both sides
+    fn settle(&mut self, fill: Fill) {
+        // neither is more than the buyer put in escrow, so neither overflows
+        let refund = fill.amount * (fill.bid_price - fill.price);
+        let payment = fill.amount * fill.price;
+
+        self.deliver(fill.seller, fill.buyer, Resource::Food, fill.amount);
+        self.deliver(fill.buyer, fill.buyer, Resource::Money, refund);
+        self.deliver(fill.buyer, fill.seller, Resource::Money, payment);
+
+        let seller_name = self.name_of(fill.seller);
+        let buyer_name = self.name_of(fill.buyer);
+        if let Some(buyer) = self.index_of(fill.buyer) {
+            let text = format!(
+                "You bought {} food from {seller_name} at ${}",
+                fill.amount, fill.price
+            );
+            self.remember(buyer, text, 2.0);
+            self.agents[buyer].history.push(ChatMessage::system(format!(
+                "You bought {} food from {seller_name} for ${} each.",
+                fill.amount, fill.price
+            )));
+        }
+        if let Some(seller) = self.index_of(fill.seller) {
+            let text = format!(
+                "You sold {} food to {buyer_name} at ${}",
+                fill.amount, fill.price
+            );
+            self.remember(seller, text, 2.0);
+            self.agents[seller]
+                .history
+                .push(ChatMessage::system(format!(
+                    "You sold {} food to {buyer_name} for ${} each.",
+                    fill.amount, fill.price
+                )));
+        }
+    }
+
+    // Hands `amount`, already taken from `from` (held in escrow or owed from a
+    // trade), to `to`. Food that won't fit in their pantry, or everything if
+    // they're gone, goes to the community pool instead, and the ledger shows
+    // where it all went. Returns how much `to` got
+    fn deliver(&mut self, from: AgentId, to: AgentId, resource: Resource, amount: u32) -> u32 {
+        let got = match self.index_of(to) {
+            Some(j) => {
+                let got = match resource {
+                    Resource::Food => amount.min(self.room(j)),
+                    Resource::Money => amount,
+                };
+                self.agents[j].credit(resource, got);
+                got
+            }
+            None => 0,
+        };
+        if from != to {
+            self.record(self.name_of(from), self.name_of(to), resource, got);
+        }
+
+        let rest = amount - got;
+        if rest > 0 {
+            self.pool.add(resource, rest);
+            self.record(
+                self.name_of(from),
+                COMMUNITY_POOL.to_string(),
+                resource,
+                rest,
+            );
+        }
+
+        got
+    }
+
+    // Takes back everything `id` has tied up in market orders and contracts
+    // they proposed, e.g. when they leave the community
+    fn withdraw_holdings(&mut self, id: AgentId) -> Goods {
+        let mut held = Goods::default();
+        let proposed: Vec<_> = self
+            .contracts
+            .open
+            .iter()
+            .filter(|c| c.proposer == id)
+            .map(|c| c.id)
+            .collect();
+        for contract_id in proposed {
+            if let Some(contract) = self.contracts.close(contract_id, ContractOutcome::Expired) {
+                self.emit(Event::ContractClosed {
+                    id: contract_id,
+                    outcome: ContractOutcome::Expired,
+                });
+                for r in RESOURCES {
+                    held.add(r, contract.give.get(r));
+                }
+            }
+        }
+
+        for order in self.market.withdraw(|owner| owner == id) {
+            let resource = order.side.escrowed();
+            held.add(resource, order.escrow());
+        }
+
+        held
+    }
+
+    // Food agent `i` still has space for
+    fn room(&self, i: usize) -> u32 {
+        self.config
+            .agent
+            .max_food
+            .saturating_sub(self.agents[i].food)
+    }
+
+    fn record(&mut self, from: String, to: String, resource: Resource, amount: u32) {
+        if amount == 0 {
+            return;
+        }
+
+        self.emit(Event::Transfer {
+            from: from.clone(),
+            to: to.clone(),
+            resource,
+            amount,
+        });
+
+        let transfer = Transfer {
+            time: self.time,
+            from,
+            to,
+            resource,
+            amount,
+        };
+        println!("[DEBUG] {transfer}");
+        self.ledger.push(transfer);
+    }
+
+    fn has_goods(&self, i: usize, goods: Goods) -> bool {
+        RESOURCES
+            .iter()
+            .all(|r| self.agents[i].balance(*r) >= goods.get(*r))
+    }
+
+    fn propose_contract(
+        &mut self,
+        i: usize,
+        other: usize,
+        give: Goods,
+        receive: Goods,
+        deadline: u32,
+    ) {
+        if !self.has_goods(i, give) {
+            let msg = format!(
+                "You can't put {give} into a contract, you only have {} food and ${}. No contract was proposed.",
+                self.agents[i].food, self.agents[i].money
+            );
+            self.agents[i].history.push(ChatMessage::system(msg));
+            return;
+        }
+
+        for r in RESOURCES {
+            self.agents[i].debit(r, give.get(r));
+        }
+
+        let name = self.agents[i].name.clone();
+        let other_name = self.agents[other].name.clone();
+        let id = self.contracts.propose(
+            self.agents[i].id,
+            self.agents[other].id,
+            give,
+            receive,
+            self.time,
+            deadline,
+        );
+
+        println!("[DEBUG] {name} proposed contract #{id} to {other_name}: {give} for {receive}");
+        if let Some(contract) = self.contracts.get(id).cloned() {
+            self.emit(Event::ContractProposed {
+                id,
+                proposer: name.clone(),
+                counterparty: other_name.clone(),
+                give: contract.give,
+                receive: contract.receive,
+                proposed: contract.proposed,
+                deadline: contract.deadline,
+            });
+        }
+
+        self.agents[i].history.push(ChatMessage::system(format!(
+            "You proposed contract #{id} to {other_name}: you give {give}, they give {receive}. Your side is held in escrow until they accept or it expires in {deadline} steps."
+        )));
+        self.agents[other].history.push(ChatMessage::system(format!(
+            "{name} has proposed contract #{id}: they give you {give}, you give them {receive}. Their side is already held in escrow. Use AcceptContract or RejectContract with contract_id {id} within {deadline} steps."
+        )));
+    }
+
+    fn accept_contract(&mut self, i: usize, id: u32) {
+        let contract = match self.contracts.get(id) {
+            Some(c) if c.counterparty == self.agents[i].id => c.clone(),
+            _ => {
+                self.agents[i].history.push(ChatMessage::system(format!(
+                    "There is no contract #{id} waiting for you."
+                )));
+                return;
+            }
+        };
+
+        let Some(proposer) = self.index_of(contract.proposer) else {
+            self.refund_contract(id, ContractOutcome::Expired);
+            return;
+        };
+
+        if !self.has_goods(i, contract.receive) {
+            let msg = format!(
+                "Contract #{id} needs you to give {}, but you only have {} food and ${}.",
+                contract.receive, self.agents[i].food, self.agents[i].money
+            );
+            self.agents[i].history.push(ChatMessage::system(msg));
+            return;
+        }
+
+        self.contracts.close(id, ContractOutcome::Executed);
+        self.emit(Event::ContractClosed {
+            id,
+            outcome: ContractOutcome::Executed,
+        });
+
+        // food that won't fit in either pantry goes to the pool
+        let (mut given, mut received) = (Goods::default(), Goods::default());
+        for r in RESOURCES {
+            self.agents[i].debit(r, contract.receive.get(r));
+            let got = self.deliver(
+                contract.counterparty,
+                contract.proposer,
+                r,
+                contract.receive.get(r),
+            );
+            received.add(r, got);
+            let got = self.deliver(
+                contract.proposer,
+                contract.counterparty,
+                r,
+                contract.give.get(r),
+            );
+            given.add(r, got);
+        }
+
+        println!(
+            "[DEBUG] contract #{id} from step {}: Executed",
+            contract.proposed
+        );
+
+        let proposer_name = self.agents[proposer].name.clone();
+        let counterparty_name = self.agents[i].name.clone();
+        let text = format!(
+            "You traded {} for {} with {proposer_name} (contract #{id})",
+            contract.receive, contract.give
+        );
+        self.remember(i, text, 6.0);
+        let text = format!(
+            "You traded {} for {} with {counterparty_name} (contract #{id})",
+            contract.give, contract.receive
+        );
+        self.remember(proposer, text, 6.0);
+        self.relationships
+            .adjust(contract.proposer, contract.counterparty, 0.15, 0.05);
+        self.relationships
+            .adjust(contract.counterparty, contract.proposer, 0.15, 0.05);
+
+        self.agents[i].history.push(ChatMessage::system(format!(
+            "Contract #{id} is done: you gave {proposer_name} {} and received {given}.",
+            contract.receive
+        )));
+        self.agents[proposer]
+            .history
+            .push(ChatMessage::system(format!(
+                "{counterparty_name} accepted contract #{id}: you received {received} for your {}.",
+                contract.give
+            )));
+    }
+
+    // Closes a contract without trading, returning the escrow to the proposer
+    fn refund_contract(&mut self, id: u32, outcome: ContractOutcome) {
+        let Some(contract) = self.contracts.close(id, outcome) else {
+            return;
+        };
+        self.emit(Event::ContractClosed { id, outcome });
+
+        println!(
+            "[DEBUG] contract #{id} from step {}: {outcome:?}",
+            contract.proposed
+        );
+
+        // if the proposer has gone, their stake goes to the pool
+        let mut returned = Goods::default();
+        for r in RESOURCES {
+            let got = self.deliver(
+                contract.proposer,
+                contract.proposer,
+                r,
+                contract.give.get(r),
+            );
+            returned.add(r, got);
+        }
+
+        if let Some(proposer) = self.index_of(contract.proposer) {
+            let counterparty = self.name_of(contract.counterparty);
+            let reason = match outcome {
+                ContractOutcome::Rejected => format!("{counterparty} rejected it"),
+                _ => "it was not accepted in time".to_string(),
+            };
+            let text =
+                format!("Your contract #{id} with {counterparty} fell through because {reason}");
+            self.remember(proposer, text, 6.0);
+            // letting it lapse is a bit worse than saying no
+            let trust = match outcome {
+                ContractOutcome::Rejected => -0.05,
+                _ => -0.1,
+            };
+            self.relationships
+                .adjust(contract.proposer, contract.counterparty, trust, -0.05);
+            self.agents[proposer]
+                .history
+                .push(ChatMessage::system(format!(
+                    "Contract #{id} with {counterparty} is off because {reason}. {returned} of your {} has been returned.",
+                    contract.give
+                )));
+        }
+    }
+
+    fn expire_contracts(&mut self) {
+        let stale = self
+            .contracts
+            .stale(self.time, |id| self.index_of(id).is_some());
+
+        for id in stale {
+            self.refund_contract(id, ContractOutcome::Expired);
+        }
+    }
+
+    fn clear_market(&mut self) {
+        // nobody trades with someone who has gone
+        let living: Vec<_> = self.agents.iter().map(|a| a.id).collect();
+        for order in self.market.withdraw(|owner| !living.contains(&owner)) {
+            let resource = order.side.escrowed();
+            self.deliver(order.owner, order.owner, resource, order.escrow());
+        }
+
+        let room: HashMap<_, _> = (0..self.agents.len())
+            .map(|i| (self.agents[i].id, self.room(i)))
+            .collect();
+        let fills = self
+            .market
+            .clear(self.time, |owner| room.get(&owner).copied().unwrap_or(0));
+        for fill in fills {
+            self.settle(fill);
+        }
+
+        let expired = self
+            .market
+            .expire(self.time, self.config.market.order_lifetime);
+
+        for order in expired {
+            let resource = order.side.escrowed();
+            let escrow = order.escrow();
+            let got = self.deliver(order.owner, order.owner, resource, escrow);
+            if let Some(owner) = self.index_of(order.owner) {
+                let mut msg = format!(
+                    "Nobody took your order #{}, so {} was returned to you.",
+                    order.id,
+                    resource.amount(got)
+                );
+                if got < escrow {
+                    msg.push_str(&format!(
+                        " The other {} didn't fit in your pantry and went to the community pool.",
+                        resource.amount(escrow - got)
+                    ));
+                }
+                self.agents[owner].history.push(ChatMessage::system(msg));
+            }
+        }
+    }
+
+    /// Number of invalid responses from every agent, living or dead
+    pub fn error_report(&self) -> Vec<(String, u32)> {
+        self.retired_errors
+            .iter()
+            .cloned()
+            .chain(self.agents.iter().map(|a| (a.name.clone(), a.errors)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{action::Action, backend::MockBackend, governance::Proposal};
+
+    // keeps every event, for checking what happened
+    struct Recorder(Arc<Mutex<Vec<Event>>>);
+
+    impl EventSink for Recorder {
+        fn emit(&mut self, _: u32, event: &Event) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(event.clone());
+            Ok(())
+        }
+    }
+
+    // A community of Ann, Bob, Cal... whose every response is scripted
+    struct Sim {
+        env: Environment,
+        backend: Arc<MockBackend>,
+        events: Arc<Mutex<Vec<Event>>>,
+    }
+
+    impl Sim {
+        fn new(population: usize, tweak: impl FnOnce(&mut Config)) -> Self {
+            let mut config = Config {
+                seed: Some(7),
+                population,
+                ..Config::default()
+            };
+            // so the script is answered in roster order
+            config.backend.max_in_flight = 1;
+            config.memory.interval = 0;
+            config.conversation.max_turns = 3;
+            config.meetings.rounds = 1;
+            tweak(&mut config);
+
+            let backend = Arc::new(MockBackend::new(7));
+            let mut env = Environment::create(backend.clone(), Arc::new(config));
+            let names = ["Ann", "Bob", "Cal", "Dee"];
+            for (k, name) in names.iter().take(population).enumerate() {
+                env.agents[k].name = name.to_string();
+                env.agents[k].food = 10;
+                env.agents[k].food_ability = 4.0;
+                env.lineage[k].name = name.to_string();
+            }
+
+            let events = Arc::default();
+            env.set_sink(Box::new(Recorder(Arc::clone(&events))));
+            Self {
+                env,
+                backend,
+                events,
+            }
+        }
+
+        // Runs a step that uses up exactly `script`
+        async fn step(&mut self, script: Vec<Value>) {
+            for response in script {
+                self.backend.push(response);
+            }
+            self.env.run_timestep().await.unwrap();
+            assert_eq!(self.backend.remaining(), 0, "the script wasn't used up");
+        }
+
+        fn agent(&self, name: &str) -> &Agent {
+            self.env.agents.iter().find(|a| a.name == name).unwrap()
+        }
+
+        fn events(&self) -> Vec<Event> {
+            std::mem::take(&mut self.events.lock().unwrap())
+        }
+
+        // everything anyone has, including what's held for them
+        fn total(&self, r: Resource) -> u32 {
+            let held: u32 = self.env.agents.iter().map(|a| a.balance(r)).sum();
+            let orders: u32 = self
+                .env
+                .market
+                .orders
+                .iter()
+                .filter(|o| o.side.escrowed() == r)
+                .map(|o| o.escrow())
+                .sum();
+            let contracts: u32 = self.env.contracts.open.iter().map(|c| c.give.get(r)).sum();
+            held + orders + contracts + self.env.pool.get(r)
+        }
+    }
+
+    fn act(action: &str, args: Value) -> Value {
+        json!({"thinking": "", "action": action, "args": args})
+    }
+
+    fn idle() -> Value {
+        act("Idle", json!({}))
+    }
+
+    #[tokio::test]
+    async fn work_and_make_food() {
+        let mut sim = Sim::new(2, |_| {});
+        sim.step(vec![act("Work", json!({})), act("MakeFood", json!({}))])
+            .await;
+
+        let (ann, bob) = (sim.agent("Ann"), sim.agent("Bob"));
+        assert_eq!((ann.money, ann.food, ann.age), (16, 9, 1));
+        assert_eq!((bob.money, bob.food, bob.age), (10, 13, 1));
+    }
+
+    #[tokio::test]
+    async fn broadcasts_reach_everyone_else() {
+        let mut sim = Sim::new(3, |_| {});
+        sim.step(vec![
+            act("Broadcast", json!({"message": "free food"})),
+            idle(),
+            idle(),
+        ])
+        .await;
+
+        for name in ["Bob", "Cal"] {
+            assert!(sim.agent(name).history.iter().any(|m| m
+                .content
+                .contains("Ann has responded! They said 'free food'")));
+        }
+    }
+
+    #[tokio::test]
+    async fn backend_failures_fall_back_instead_of_ending_the_run() {
+        let mut sim = Sim::new(2, |config| config.retry.fallback = Action::MakeFood);
+        sim.env.attach(backend::detached(), sim.env.config.clone());
+        sim.env.run_timestep().await.unwrap();
+
+        for agent in &sim.env.agents {
+            assert_eq!((agent.errors, agent.food), (3, 13));
+        }
+    }
+
+    #[tokio::test]
+    async fn gifts_move_goods_without_making_any() {
+        let mut sim = Sim::new(2, |_| {});
+        sim.env.agents[0].food = 19;
+        let food = sim.total(Resource::Food);
+
+        sim.step(vec![
+            act(
+                "GiveMoney",
+                json!({"who_to_interact_with": "Bob", "amount": 3}),
+            ),
+            act(
+                "GiveFood",
+                json!({"who_to_interact_with": "Ann", "amount": 5}),
+            ),
+        ])
+        .await;
+
+        let (ann, bob) = (sim.agent("Ann"), sim.agent("Bob"));
+        assert_eq!((ann.money, bob.money), (7, 13));
+        // only one more fit in Ann's pantry
+        assert_eq!((ann.food, bob.food), (19, 8));
+        assert_eq!(sim.total(Resource::Food), food - 2);
+        assert_eq!(sim.total(Resource::Money), 20);
+    }
+
+    #[tokio::test]
+    async fn gifts_beyond_the_balance_are_refused() {
+        let mut sim = Sim::new(2, |_| {});
+        sim.step(vec![
+            act(
+                "GiveMoney",
+                json!({"who_to_interact_with": "Bob", "amount": 11}),
+            ),
+            idle(),
+        ])
+        .await;
+
+        assert_eq!(sim.agent("Ann").money, 10);
+        assert_eq!(sim.agent("Bob").money, 10);
+    }
+
+    #[tokio::test]
+    async fn market_orders_cross_at_one_price() {
+        let mut sim = Sim::new(2, |_| {});
+        sim.step(vec![
+            act("PostOffer", json!({"amount": 3, "price": 2})),
+            act("PostBid", json!({"amount": 2, "price": 4})),
+        ])
+        .await;
+
+        // halfway between 4 and 2, and Bob gets back what he escrowed over it
+        let (ann, bob) = (sim.agent("Ann"), sim.agent("Bob"));
+        assert_eq!((ann.food, ann.money), (6, 16));
+        assert_eq!((bob.food, bob.money), (11, 4));
+        assert_eq!(sim.env.market.orders.len(), 1);
+        assert_eq!(sim.total(Resource::Food), 18);
+        assert_eq!(sim.total(Resource::Money), 20);
+
+        // the last of Ann's offer, bought outright
+        sim.step(vec![idle(), act("AcceptOffer", json!({"order_id": 0}))])
+            .await;
+        let (ann, bob) = (sim.agent("Ann"), sim.agent("Bob"));
+        assert_eq!((ann.food, ann.money), (5, 18));
+        assert_eq!((bob.food, bob.money), (11, 2));
+        assert!(sim.env.market.orders.is_empty());
+    }
+
+    #[tokio::test]
+    async fn nobody_buys_more_than_fits_in_their_pantry() {
+        let mut sim = Sim::new(2, |_| {});
+        sim.env.agents[1].food = 19;
+        sim.step(vec![
+            act("PostOffer", json!({"amount": 5, "price": 1})),
+            act("PostBid", json!({"amount": 5, "price": 1})),
+        ])
+        .await;
+
+        // Bob eats one before the market clears, making room for two
+        let bob = sim.agent("Bob");
+        assert_eq!((bob.food, bob.money), (20, 5));
+        let bid = sim.env.market.orders.iter().find(|o| o.side == Side::Bid);
+        assert_eq!(bid.unwrap().amount, 3);
+        assert_eq!(sim.total(Resource::Money), 20);
+
+        // a full pantry is shown as full
+        sim.step(vec![idle(), idle()]).await;
+        assert!(sim
+            .agent("Bob")
+            .history
+            .iter()
+            .any(|m| m.content.contains("you have 20 food (max 20,")));
+    }
+
+    #[tokio::test]
+    async fn unaffordable_bids_are_refused_not_overflowed() {
+        let mut sim = Sim::new(2, |_| {});
+        sim.step(vec![
+            act("PostBid", json!({"amount": 100_000, "price": 100_000})),
+            idle(),
+        ])
+        .await;
+
+        assert!(sim.env.market.orders.is_empty());
+        assert_eq!(sim.agent("Ann").money, 10);
+    }
+
+    fn contract() -> Value {
+        act(
+            "ProposeContract",
+            json!({"who_to_interact_with": "Bob", "give": {"food": 2}, "receive": {"money": 3}, "deadline": 1}),
+        )
+    }
+
+    #[tokio::test]
+    async fn accepted_contracts_swap_both_sides() {
+        let mut sim = Sim::new(2, |_| {});
+        sim.step(vec![contract(), idle()]).await;
+        assert_eq!(sim.agent("Ann").food, 7);
+        assert_eq!(sim.env.contracts.open.len(), 1);
+
+        sim.step(vec![
+            idle(),
+            act("AcceptContract", json!({"contract_id": 0})),
+        ])
+        .await;
+        let (ann, bob) = (sim.agent("Ann"), sim.agent("Bob"));
+        assert_eq!((ann.food, ann.money), (6, 13));
+        assert_eq!((bob.food, bob.money), (10, 7));
+        assert!(sim.events().iter().any(|e| matches!(
+            e,
+            Event::ContractClosed {
+                outcome: ContractOutcome::Executed,
+                ..
+            }
+        )));
+    }
+
+    #[tokio::test]
+    async fn rejected_and_expired_contracts_are_refunded() {
+        let mut sim = Sim::new(2, |_| {});
+        sim.step(vec![contract(), idle()]).await;
+        sim.step(vec![
+            idle(),
+            act("RejectContract", json!({"contract_id": 0})),
+        ])
+        .await;
+        assert_eq!(sim.agent("Ann").food, 8);
+        assert!(sim.env.contracts.open.is_empty());
+
+        sim.step(vec![contract(), idle()]).await;
+        sim.step(vec![idle(), idle()]).await;
+        assert_eq!(sim.env.contracts.open.len(), 1);
+        sim.step(vec![idle(), idle()]).await;
+        assert!(sim.env.contracts.open.is_empty());
+        assert_eq!(sim.agent("Ann").food, 5);
+    }
+
+    #[tokio::test]
+    async fn escrow_never_follows_a_name_to_someone_else() {
+        let mut sim = Sim::new(3, |_| {});
+        sim.step(vec![contract(), idle(), idle()]).await;
+
+        // Ann vanishes and Cal somehow ends up with her name
+        sim.env.agents.remove(0);
+        sim.env.agents[1].name = "Ann".to_string();
+        sim.env.lineage[2].name = "Ann".to_string();
+        sim.events();
+
+        sim.step(vec![idle(), idle()]).await;
+        assert!(sim.env.contracts.open.is_empty());
+        assert!(sim.events().iter().any(|e| matches!(
+            e,
+            Event::Transfer { from, to, resource: Resource::Food, amount: 2 }
+                if from == "Ann" && to == COMMUNITY_POOL
+        )));
+        // so all Cal got was their share of the pool, same as Bob
+        assert_eq!(sim.agent("Ann").food, sim.agent("Bob").food);
+    }
+
+    #[tokio::test]
+    async fn turned_down_proposals_cost_nothing() {
+        let mut sim = Sim::new(2, |config| config.reproduction.min_age = 0);
+        sim.step(vec![
+            act(
+                "Reproduce",
+                json!({"who_to_interact_with": "Bob", "message": "baby?"}),
+            ),
+            idle(),
+            json!(false),
+        ])
+        .await;
+
+        assert_eq!(sim.env.agents.len(), 2);
+        assert_eq!((sim.agent("Ann").food, sim.agent("Ann").money), (9, 10));
+    }
+
+    #[tokio::test]
+    async fn children_cant_work_even_as_a_fallback() {
+        let mut sim = Sim::new(2, |config| {
+            config.reproduction.min_age = 0;
+            config.retry.max_retries = 0;
+            config.retry.fallback = Action::Work;
+        });
+        sim.step(vec![
+            act(
+                "Reproduce",
+                json!({"who_to_interact_with": "Bob", "message": "baby?"}),
+            ),
+            idle(),
+            json!(true),
+        ])
+        .await;
+        let baby = sim.env.agents[2].name.clone();
+
+        sim.step(vec![idle(), idle(), act("Work", json!({}))]).await;
+        let child = sim.agent(&baby);
+        assert_eq!((child.money, child.errors), (10, 1));
+        assert!(sim.events().iter().any(|e| matches!(
+            e,
+            Event::ActionTaken { agent, action: ValidAction::Idle, .. } if *agent == baby
+        )));
+    }
+
+    #[tokio::test]
+    async fn the_starving_and_the_old_die_and_leave_their_estate() {
+        let mut sim = Sim::new(3, |config| config.agent.death_age = 5);
+        sim.env.agents[0].food = 0;
+        sim.env.agents[1].age = 4;
+        sim.step(vec![idle(), idle(), idle()]).await;
+
+        let deaths: Vec<_> = sim
+            .events()
+            .into_iter()
+            .filter_map(|e| match e {
+                Event::Death { name, cause, .. } => Some((name, cause)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            deaths,
+            [
+                ("Ann".to_string(), DeathCause::Starvation),
+                ("Bob".to_string(), DeathCause::OldAge)
+            ]
+        );
+        assert_eq!(sim.env.agents.len(), 1);
+        assert!(sim.env.lineage[..2].iter().all(|r| r.died == Some(0)));
+
+        // with no children, both estates went to Cal by way of the pool
+        let cal = sim.agent("Cal");
+        assert_eq!((cal.food, cal.money), (18, 30));
+    }
+
+    #[tokio::test]
+    async fn typed_names_are_guessed_at_but_never_the_dead() {
+        let mut sim = Sim::new(3, |config| config.retry.max_retries = 0);
+        sim.env.agents[2].food = 0;
+        sim.step(vec![
+            act(
+                "GiveMoney",
+                json!({"who_to_interact_with": "bo", "amount": 1}),
+            ),
+            idle(),
+            idle(),
+        ])
+        .await;
+        // plus half of what Cal left
+        assert_eq!(sim.agent("Bob").money, 16);
+        assert!(sim
+            .agent("Ann")
+            .history
+            .iter()
+            .any(|m| m.content.contains("assumed you meant Bob")));
+
+        sim.events();
+        sim.step(vec![
+            act(
+                "GiveMoney",
+                json!({"who_to_interact_with": "cal", "amount": 1}),
+            ),
+            idle(),
+        ])
+        .await;
+        assert!(sim.events().iter().any(|e| matches!(
+            e,
+            Event::InvalidAction { agent, error } if agent == "Ann" && error.contains("Cal has died")
+        )));
+    }
+
+    #[tokio::test]
+    async fn endless_cooldowns_dont_overflow() {
+        let mut sim = Sim::new(2, |config| {
+            config.reproduction.min_age = 0;
+            config.reproduction.cooldown = u32::MAX;
+        });
+        sim.env.agents[0].last_child = Some(1);
+        sim.step(vec![
+            act(
+                "Reproduce",
+                json!({"who_to_interact_with": "Bob", "message": "baby?"}),
+            ),
+            idle(),
+        ])
+        .await;
+
+        assert_eq!(sim.env.agents.len(), 2);
+        assert!(sim
+            .agent("Ann")
+            .history
+            .iter()
+            .any(|m| m.content.contains("still recovering")));
+    }
+
+    #[tokio::test]
+    async fn actions_resolve_in_roster_order() {
+        let mut sim = Sim::new(3, |_| {});
+        sim.step(vec![
+            act(
+                "GiveMoney",
+                json!({"who_to_interact_with": "Bob", "amount": 5}),
+            ),
+            // only affordable thanks to Ann
+            act(
+                "GiveMoney",
+                json!({"who_to_interact_with": "Cal", "amount": 15}),
+            ),
+            idle(),
+        ])
+        .await;
+
+        assert_eq!(sim.agent("Ann").money, 5);
+        assert_eq!(sim.agent("Bob").money, 0);
+        assert_eq!(sim.agent("Cal").money, 25);
+    }
+
+    #[tokio::test]
+    async fn babies_are_born_after_everyone_acts_and_age_from_next_step() {
+        let mut sim = Sim::new(2, |config| config.reproduction.min_age = 0);
+        sim.step(vec![
+            act(
+                "Reproduce",
+                json!({"who_to_interact_with": "Bob", "message": "baby?"}),
+            ),
+            idle(),
+            json!(true),
+        ])
+        .await;
+
+        assert_eq!(sim.env.agents.len(), 3);
+        let (ann, bob, baby) = (&sim.env.agents[0], &sim.env.agents[1], &sim.env.agents[2]);
+        assert_eq!((ann.food, ann.money), (6, 5));
+        assert_eq!((bob.food, bob.money), (7, 5));
+        // born after everyone ate, so it hasn't yet
+        assert_eq!((baby.food, baby.money, baby.age), (5, 10, 0));
+        assert_eq!(baby.parents, Some([ann.id, bob.id]));
+        assert!(baby.history[0]
+            .content
+            .contains("named as follows:\nAnn\nBob\n"));
+        assert!(sim
+            .events()
+            .iter()
+            .any(|e| matches!(e, Event::Birth { .. })));
+
+        sim.step(vec![idle(), idle(), idle()]).await;
+        let baby = &sim.env.agents[2];
+        assert_eq!((baby.food, baby.age), (4, 1));
+    }
+
+    fn reply(message: &str, end_conversation: bool) -> Value {
+        json!({"message": message, "end_conversation": end_conversation})
+    }
+
+    #[tokio::test]
+    async fn conversations_go_back_and_forth() {
+        let mut sim = Sim::new(2, |_| {});
+        sim.step(vec![
+            act(
+                "Converse",
+                json!({"who_to_interact_with": "Bob", "message": "hi"}),
+            ),
+            idle(),
+            reply("hello", false),
+            reply("bye", true),
+        ])
+        .await;
+
+        let conversation = sim
+            .events()
+            .into_iter()
+            .find_map(|e| match e {
+                Event::Conversation { lines, ending, .. } => Some((lines, ending)),
+                _ => None,
+            })
+            .unwrap();
+        let said: Vec<_> = conversation.0.iter().map(|l| l.message.as_str()).collect();
+        assert_eq!(said, ["hi", "hello", "bye"]);
+        assert_eq!(conversation.1, Ending::Goodbye);
+
+        assert!(sim
+            .agent("Bob")
+            .history
+            .iter()
+            .any(|m| m.content.contains("Ann: hi\nBob: hello\nAnn: bye")));
+    }
+
+    #[tokio::test]
+    async fn conversations_stop_at_the_turn_limit() {
+        let mut sim = Sim::new(2, |_| {});
+        sim.step(vec![
+            act(
+                "Converse",
+                json!({"who_to_interact_with": "Bob", "message": "hi"}),
+            ),
+            idle(),
+            reply("hello", false),
+            reply("how are you?", false),
+        ])
+        .await;
+
+        assert!(sim.events().iter().any(|e| matches!(
+            e,
+            Event::Conversation { lines, ending: Ending::TurnLimit, .. } if lines.len() == 3
+        )));
+    }
+
+    #[tokio::test]
+    async fn meetings_vote_on_their_question() {
+        let mut sim = Sim::new(3, |_| {});
+        sim.step(vec![
+            act(
+                "CallMeeting",
+                json!({"message": "the harvest", "question": "Should we share food?"}),
+            ),
+            idle(),
+            idle(),
+            reply("let's share", false),
+            reply("agreed", false),
+            reply("no way", false),
+            json!(true),
+            json!(true),
+            json!(false),
+        ])
+        .await;
+
+        let (participants, lines, vote) = sim
+            .events()
+            .into_iter()
+            .find_map(|e| match e {
+                Event::Meeting {
+                    participants,
+                    lines,
+                    vote,
+                    ..
+                } => Some((participants, lines, vote)),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(participants, ["Ann", "Bob", "Cal"]);
+        assert_eq!(lines.len(), 4);
+        let vote = vote.unwrap();
+        assert!(vote.passed);
+        assert_eq!(
+            (vote.yes, vote.no),
+            (vec!["Ann".into(), "Bob".into()], vec!["Cal".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn food_taxes_are_voted_in_and_collected() {
+        let mut sim = Sim::new(3, |_| {});
+        sim.step(vec![
+            act(
+                "ProposeRule",
+                json!({"rule": "FoodTax", "amount": 50, "message": "share"}),
+            ),
+            idle(),
+            idle(),
+        ])
+        .await;
+        assert_eq!(sim.env.governance.food_tax(), 0);
+
+        sim.step(vec![
+            json!(true),
+            json!(true),
+            json!(false),
+            act("MakeFood", json!({})),
+            idle(),
+            idle(),
+        ])
+        .await;
+        assert_eq!(sim.env.governance.food_tax(), 50);
+        // two of the four made went to the pool, too little to share out
+        assert_eq!(sim.agent("Ann").food, 10);
+        assert_eq!(sim.env.pool.food, 2);
+    }
+
+    #[tokio::test]
+    async fn taxed_food_made_over_the_cap_doesnt_underflow() {
+        let mut sim = Sim::new(1, |_| {});
+        sim.env.governance.enact(
+            Proposal {
+                id: 0,
+                proposer: "Ann".into(),
+                rule: Rule::FoodTax { percent: 50 },
+                message: String::new(),
+                proposed: 0,
+            },
+            0,
+        );
+        sim.env.agents[0].food = 25;
+        sim.step(vec![act("MakeFood", json!({}))]).await;
+
+        // clamped to the cap, so nothing was made or taxed
+        assert_eq!(sim.agent("Ann").food, 19);
+        assert_eq!(sim.env.pool.food, 0);
+    }
+
+    #[tokio::test]
+    async fn exiles_forfeit_everything_to_the_pool() {
+        let mut sim = Sim::new(3, |_| {});
+        sim.step(vec![
+            act("PostOffer", json!({"amount": 2, "price": 9})),
+            idle(),
+            act(
+                "ProposeRule",
+                json!({"rule": "Exile", "who_to_interact_with": "Ann", "message": "begone"}),
+            ),
+        ])
+        .await;
+
+        sim.step(vec![json!(false), json!(true), json!(true), idle(), idle()])
+            .await;
+        assert!(sim.env.agents.iter().all(|a| a.name != "Ann"));
+        assert!(sim.env.lineage[0].exiled);
+        assert!(sim.env.market.orders.is_empty());
+
+        // 9 food (2 of it on the market) and $10, split between two
+        for name in ["Bob", "Cal"] {
+            let agent = sim.agent(name);
+            assert_eq!((agent.food, agent.money), (12, 15));
+        }
+        assert_eq!(sim.env.pool, Goods { food: 1, money: 0 });
     }
 }
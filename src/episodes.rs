@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+// too common to say anything about what a memory is about
+const STOPWORDS: &[&str] = &[
+    "the", "and", "you", "your", "for", "are", "was", "were", "with", "that", "this", "have",
+    "has", "had", "they", "them", "their", "from", "but", "not", "can", "will", "would", "what",
+    "who", "all", "any", "its", "our", "out", "get", "got", "one", "each", "food", "money",
+];
+
+/// Something that happened to an agent that's worth remembering for longer
+/// than it stays in the context window
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Episode {
+    pub time: u32,
+    pub text: String,
+    // 0-10, how much this should stick
+    pub importance: f32,
+    keywords: Vec<String>,
+}
+
+/// An agent's long-term memory. Episodes are scored against a query by
+/// shared keywords, importance and how recent they are, and once it's full
+/// the least important, oldest ones are forgotten
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct EpisodicMemory {
+    episodes: Vec<Episode>,
+}
+
+impl EpisodicMemory {
+    pub fn record(&mut self, time: u32, text: String, importance: f32, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+
+        self.episodes.push(Episode {
+            time,
+            keywords: keywords(&text),
+            text,
+            importance: importance.clamp(0.0, 10.0),
+        });
+
+        while self.episodes.len() > capacity {
+            let weakest = self
+                .episodes
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    let a = a.importance * recency(a.time, time);
+                    let b = b.importance * recency(b.time, time);
+                    a.total_cmp(&b)
+                })
+                .map(|(i, _)| i)
+                .unwrap();
+            self.episodes.remove(weakest);
+        }
+    }
+
+    /// The `k` episodes most worth remembering right now, oldest first
+    pub fn recall(&self, query: &str, now: u32, k: usize) -> Vec<&Episode> {
+        let query = keywords(query);
+
+        let mut scored: Vec<_> = self
+            .episodes
+            .iter()
+            .map(|e| {
+                let relevance = e.keywords.iter().filter(|w| query.contains(w)).count() as f32;
+                (relevance + e.importance / 10.0 + recency(e.time, now), e)
+            })
+            .collect();
+        scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        let mut recalled: Vec<_> = scored.into_iter().take(k).map(|(_, e)| e).collect();
+        recalled.sort_by_key(|e| e.time);
+        recalled
+    }
+}
+
+/// How much a message is worth remembering. Anything that sounds like a
+/// commitment matters more, so promises can be held against people later
+pub fn message_importance(message: &str) -> f32 {
+    const COMMITMENTS: &[&str] = &[
+        "promise",
+        "deal",
+        "owe",
+        "will give",
+        "i'll",
+        "pay",
+        "trade",
+        "agree",
+    ];
+
+    let message = message.to_lowercase();
+    if COMMITMENTS.iter().any(|c| message.contains(c)) {
+        6.0
+    } else {
+        2.0
+    }
+}
+
+// 1 for now, fading towards 0 over a few hundred steps
+fn recency(time: u32, now: u32) -> f32 {
+    0.99f32.powi(now.saturating_sub(time) as i32)
+}
+
+fn keywords(text: &str) -> Vec<String> {
+    let mut words: Vec<_> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() >= 3)
+        .map(str::to_lowercase)
+        .filter(|w| !STOPWORDS.contains(&w.as_str()))
+        .collect();
+    words.sort();
+    words.dedup();
+    words
+}
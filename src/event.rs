@@ -0,0 +1,186 @@
+use std::{
+    fmt::Debug,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+
+use crate::{
+    action::ValidAction,
+    contract::ContractOutcome,
+    conversation::{Ending, Line},
+    governance::Rule,
+    ledger::{Goods, Resource},
+    market::Side,
+    meeting::Vote,
+};
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeathCause {
+    Starvation,
+    OldAge,
+}
+
+/// Something that happened during a timestep, for analysing runs afterwards
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type")]
+pub enum Event {
+    StepStarted {
+        population: usize,
+    },
+    ActionTaken {
+        agent: String,
+        thinking: String,
+        action: ValidAction<String>,
+    },
+    InvalidAction {
+        agent: String,
+        error: String,
+    },
+    Conversation {
+        participants: [String; 2],
+        lines: Vec<Line>,
+        ending: Ending,
+    },
+    Broadcast {
+        from: String,
+        message: String,
+    },
+    Meeting {
+        chair: String,
+        participants: Vec<String>,
+        lines: Vec<Line>,
+        vote: Option<Vote>,
+    },
+    Transfer {
+        from: String,
+        to: String,
+        resource: Resource,
+        amount: u32,
+    },
+    OrderPosted {
+        id: u32,
+        owner: String,
+        side: Side,
+        amount: u32,
+        price: u32,
+    },
+    ContractProposed {
+        id: u32,
+        proposer: String,
+        counterparty: String,
+        give: Goods,
+        receive: Goods,
+        proposed: u32,
+        deadline: u32,
+    },
+    RuleProposed {
+        id: u32,
+        proposer: String,
+        rule: Rule,
+        message: String,
+    },
+    RuleVoted {
+        id: u32,
+        rule: Rule,
+        vote: Vote,
+    },
+    Exiled {
+        name: String,
+    },
+    ContractClosed {
+        id: u32,
+        outcome: ContractOutcome,
+    },
+    ReproductionProposed {
+        from: String,
+        to: String,
+        accepted: bool,
+    },
+    Birth {
+        name: String,
+        parents: [String; 2],
+    },
+    Death {
+        name: String,
+        age: u32,
+        cause: DeathCause,
+    },
+    StepFinished {
+        // wall-clock, including the decision phase
+        step_secs: f64,
+        decide_secs: f64,
+        // generated while deciding
+        tokens: u64,
+    },
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    time: u32,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Where events go. Implement this to feed a run into something other than
+/// a file
+pub trait EventSink: Send {
+    fn emit(&mut self, time: u32, event: &Event) -> anyhow::Result<()>;
+
+    // called at the end of every timestep
+    fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Discards everything
+pub struct NullSink;
+
+impl EventSink for NullSink {
+    fn emit(&mut self, _: u32, _: &Event) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn null_sink() -> Box<dyn EventSink> {
+    Box::new(NullSink)
+}
+
+/// Writes one JSON object per line, e.g.
+/// `{"time":3,"type":"Death","name":"Emma","age":80,"cause":"OldAge"}`
+pub struct JsonlSink {
+    writer: BufWriter<File>,
+}
+
+impl JsonlSink {
+    // `append` keeps what's already there, for resumed runs
+    pub fn open(path: &Path, append: bool) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+}
+
+impl EventSink for JsonlSink {
+    fn emit(&mut self, time: u32, event: &Event) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.writer, &Record { time, event })?;
+        self.writer.write_all(b"\n")?;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt::{self, Display, Write};
+
+use ollama_rs::generation::parameters::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::agent::AgentId;
+
+/// The kinds of rule an agent can propose
+#[derive(JsonSchema, Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum RuleKind {
+    FoodTax,
+    Exile,
+}
+
+/// Something the whole community has to go along with once it's voted in
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Rule {
+    // this percentage of all food anyone makes goes to the community pool
+    FoodTax { percent: u32 },
+    // made to leave the community. `name` is who they were when it was
+    // proposed, for showing people
+    Exile { who: AgentId, name: String },
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::FoodTax { percent } => write!(
+                f,
+                "{percent}% of all food anyone makes goes to the community pool"
+            ),
+            Rule::Exile { name, .. } => write!(f, "{name} is exiled from the community"),
+        }
+    }
+}
+
+/// A rule waiting to be voted on
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Proposal {
+    pub id: u32,
+    pub proposer: String,
+    pub rule: Rule,
+    // why they want it
+    pub message: String,
+    pub proposed: u32,
+}
+
+/// A rule that passed
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Enacted {
+    pub id: u32,
+    pub proposer: String,
+    pub rule: Rule,
+    pub passed: u32,
+}
+
+/// Proposals waiting for a vote, and the rules in force
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Governance {
+    next_id: u32,
+    proposals: Vec<Proposal>,
+    pub rules: Vec<Enacted>,
+}
+
+impl Governance {
+    pub fn propose(&mut self, proposer: String, rule: Rule, message: String, time: u32) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.proposals.push(Proposal {
+            id,
+            proposer,
+            rule,
+            message,
+            proposed: time,
+        });
+
+        id
+    }
+
+    /// Proposals from before `time`, which everyone votes on now
+    pub fn take_due(&mut self, time: u32) -> Vec<Proposal> {
+        let (due, later) = std::mem::take(&mut self.proposals)
+            .into_iter()
+            .partition(|p| p.proposed < time);
+        self.proposals = later;
+        due
+    }
+
+    /// Puts a passed proposal into force. A new food tax replaces the old one
+    pub fn enact(&mut self, proposal: Proposal, time: u32) {
+        if matches!(proposal.rule, Rule::FoodTax { .. }) {
+            self.rules
+                .retain(|r| !matches!(r.rule, Rule::FoodTax { .. }));
+        }
+
+        self.rules.push(Enacted {
+            id: proposal.id,
+            proposer: proposal.proposer,
+            rule: proposal.rule,
+            passed: time,
+        });
+    }
+
+    /// Percentage of food made that is taxed, 0 if there's no tax
+    pub fn food_tax(&self) -> u32 {
+        self.rules
+            .iter()
+            .find_map(|r| match r.rule {
+                Rule::FoodTax { percent } => Some(percent),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    /// The rules in force and the proposals still to be voted on, for prompts
+    pub fn describe(&self) -> String {
+        let mut s = String::new();
+
+        if self.rules.is_empty() {
+            let _ = writeln!(s, "The community has not voted in any rules.");
+        } else {
+            let _ = writeln!(s, "Community rules, voted in by everyone:");
+            for r in &self.rules {
+                let _ = writeln!(
+                    s,
+                    "- #{}: {} (proposed by {}, passed at step {})",
+                    r.id, r.rule, r.proposer, r.passed
+                );
+            }
+        }
+
+        if !self.proposals.is_empty() {
+            let _ = writeln!(s, "Proposed rules, to be voted on next step:");
+            for p in &self.proposals {
+                let _ = writeln!(s, "- #{}: {} (proposed by {})", p.id, p.rule, p.proposer);
+            }
+        }
+
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proposals_are_numbered_from_zero_and_voted_on_next_step() {
+        let mut governance = Governance::default();
+        let tax = Rule::FoodTax { percent: 10 };
+        assert_eq!(
+            governance.propose("Ann".into(), tax.clone(), "".into(), 0),
+            0
+        );
+        assert_eq!(governance.propose("Bob".into(), tax, "".into(), 1), 1);
+
+        let due = governance.take_due(1);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, 0);
+    }
+
+    #[test]
+    fn a_new_food_tax_replaces_the_old() {
+        let mut governance = Governance::default();
+        for percent in [10, 20] {
+            governance.propose("Ann".into(), Rule::FoodTax { percent }, "".into(), 0);
+        }
+        for proposal in governance.take_due(1) {
+            governance.enact(proposal, 1);
+        }
+
+        assert_eq!(governance.rules.len(), 1);
+        assert_eq!(governance.food_tax(), 20);
+    }
+}
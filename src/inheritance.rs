@@ -0,0 +1,179 @@
+use std::{f32::consts::PI, fmt::Debug};
+
+use rand::{Rng, RngCore};
+
+use crate::config::{InheritanceConfig, InheritanceKind};
+
+/// How a child's traits (in the order of `agent::TRAITS`) come from its
+/// parents'. Implement this to try out other kinds of evolution
+pub trait InheritanceModel: Debug + Send + Sync {
+    fn inherit(&self, a: [f32; 5], b: [f32; 5], rng: &mut dyn RngCore) -> [f32; 5];
+}
+
+pub fn from_config(config: &InheritanceConfig) -> Box<dyn InheritanceModel> {
+    let model: Box<dyn InheritanceModel> = match config.kind {
+        InheritanceKind::Blend => Box::new(Blend),
+        InheritanceKind::PerTrait => Box::new(PerTrait),
+        InheritanceKind::Dominant => Box::new(Dominant {
+            dominance: config.dominance.clamp(0.0, 1.0),
+        }),
+    };
+
+    if config.mutation_sigma > 0.0 {
+        Box::new(Mutate {
+            inner: model,
+            sigma: config.mutation_sigma,
+        })
+    } else {
+        model
+    }
+}
+
+/// Every trait is the same random mix of the two parents
+#[derive(Debug)]
+pub struct Blend;
+
+impl InheritanceModel for Blend {
+    fn inherit(&self, a: [f32; 5], b: [f32; 5], rng: &mut dyn RngCore) -> [f32; 5] {
+        let a_weight = rng.gen::<f32>();
+        std::array::from_fn(|t| b[t] * (1.0 - a_weight) + a[t] * a_weight)
+    }
+}
+
+/// Each trait is its own random mix of the two parents
+#[derive(Debug)]
+pub struct PerTrait;
+
+impl InheritanceModel for PerTrait {
+    fn inherit(&self, a: [f32; 5], b: [f32; 5], rng: &mut dyn RngCore) -> [f32; 5] {
+        std::array::from_fn(|t| {
+            let a_weight = rng.gen::<f32>();
+            b[t] * (1.0 - a_weight) + a[t] * a_weight
+        })
+    }
+}
+
+/// Each trait is taken whole from one parent. The more extreme value (further
+/// from 5) is dominant and wins with probability `dominance`
+#[derive(Debug)]
+pub struct Dominant {
+    pub dominance: f32,
+}
+
+impl InheritanceModel for Dominant {
+    fn inherit(&self, a: [f32; 5], b: [f32; 5], rng: &mut dyn RngCore) -> [f32; 5] {
+        std::array::from_fn(|t| {
+            let (dominant, recessive) = if (a[t] - 5.0).abs() >= (b[t] - 5.0).abs() {
+                (a[t], b[t])
+            } else {
+                (b[t], a[t])
+            };
+
+            if rng.gen_bool(self.dominance as f64) {
+                dominant
+            } else {
+                recessive
+            }
+        })
+    }
+}
+
+/// Runs another model, then nudges every trait by Gaussian noise, keeping
+/// it within 0-10
+#[derive(Debug)]
+pub struct Mutate {
+    pub inner: Box<dyn InheritanceModel>,
+    pub sigma: f32,
+}
+
+impl InheritanceModel for Mutate {
+    fn inherit(&self, a: [f32; 5], b: [f32; 5], rng: &mut dyn RngCore) -> [f32; 5] {
+        self.inner
+            .inherit(a, b, rng)
+            .map(|t| (t + gaussian(rng) * self.sigma).clamp(0.0, 10.0))
+    }
+}
+
+// Standard normal sample, by Box-Muller
+fn gaussian(rng: &mut dyn RngCore) -> f32 {
+    // 1 - [0, 1) keeps ln away from 0
+    let u1 = 1.0 - rng.gen::<f32>();
+    let u2 = rng.gen::<f32>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    use super::*;
+
+    const A: [f32; 5] = [0.0, 2.0, 4.0, 6.0, 10.0];
+    const B: [f32; 5] = [10.0, 8.0, 4.0, 5.0, 0.0];
+
+    fn model(
+        kind: InheritanceKind,
+        mutation_sigma: f32,
+        dominance: f32,
+    ) -> Box<dyn InheritanceModel> {
+        from_config(&InheritanceConfig {
+            kind,
+            mutation_sigma,
+            dominance,
+        })
+    }
+
+    fn between(t: f32, a: f32, b: f32) -> bool {
+        t >= a.min(b) && t <= a.max(b)
+    }
+
+    #[test]
+    fn blends_stay_between_the_parents() {
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        for kind in [InheritanceKind::Blend, InheritanceKind::PerTrait] {
+            for _ in 0..100 {
+                let child = model(kind, 0.0, 0.0).inherit(A, B, &mut rng);
+                for t in 0..5 {
+                    assert!(between(child[t], A[t], B[t]), "{kind:?} {child:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blend_uses_one_mix_for_every_trait() {
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let child = Blend.inherit(A, B, &mut rng);
+        // trait 0 goes 0 -> 10 and trait 4 goes 10 -> 0, so they mirror
+        assert!((child[0] + child[4] - 10.0).abs() < 1e-4, "{child:?}");
+    }
+
+    #[test]
+    fn dominant_traits_come_whole_from_a_parent() {
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        for _ in 0..100 {
+            let child = model(InheritanceKind::Dominant, 0.0, 0.5).inherit(A, B, &mut rng);
+            for t in 0..5 {
+                assert!(child[t] == A[t] || child[t] == B[t], "{child:?}");
+            }
+        }
+
+        // with certain dominance, the trait further from 5 always wins
+        let child = model(InheritanceKind::Dominant, 0.0, 1.0).inherit(A, B, &mut rng);
+        assert_eq!(child, [0.0, 2.0, 4.0, 6.0, 10.0]);
+    }
+
+    #[test]
+    fn mutation_stays_in_range() {
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let mutate = model(InheritanceKind::Blend, 5.0, 0.0);
+        let mut changed = false;
+        for _ in 0..100 {
+            let child = mutate.inherit(A, A, &mut rng);
+            assert!(child.iter().all(|t| (0.0..=10.0).contains(t)), "{child:?}");
+            changed |= child != A;
+        }
+        assert!(changed);
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt::{self, Display};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resource {
+    Food,
+    Money,
+}
+
+pub const RESOURCES: [Resource; 2] = [Resource::Food, Resource::Money];
+
+impl Resource {
+    // "3 food" / "$3"
+    pub fn amount(&self, amount: u32) -> String {
+        match self {
+            Resource::Food => format!("{amount} food"),
+            Resource::Money => format!("${amount}"),
+        }
+    }
+}
+
+/// A bundle of food and money, e.g. one side of a contract
+#[derive(JsonSchema, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Goods {
+    #[serde(default)]
+    pub food: u32,
+    #[serde(default)]
+    pub money: u32,
+}
+
+impl Goods {
+    pub fn is_empty(&self) -> bool {
+        self.food == 0 && self.money == 0
+    }
+
+    pub fn get(&self, resource: Resource) -> u32 {
+        match resource {
+            Resource::Food => self.food,
+            Resource::Money => self.money,
+        }
+    }
+
+    pub fn add(&mut self, resource: Resource, amount: u32) {
+        match resource {
+            Resource::Food => self.food += amount,
+            Resource::Money => self.money += amount,
+        }
+    }
+
+    pub fn remove(&mut self, resource: Resource, amount: u32) {
+        match resource {
+            Resource::Food => self.food -= amount,
+            Resource::Money => self.money -= amount,
+        }
+    }
+}
+
+impl Display for Goods {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.food, self.money) {
+            (0, 0) => write!(f, "nothing"),
+            (food, 0) => write!(f, "{}", Resource::Food.amount(food)),
+            (0, money) => write!(f, "{}", Resource::Money.amount(money)),
+            (food, money) => write!(
+                f,
+                "{} and {}",
+                Resource::Food.amount(food),
+                Resource::Money.amount(money)
+            ),
+        }
+    }
+}
+
+/// A completed movement of goods from one person to another
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Transfer {
+    pub time: u32,
+    pub from: String,
+    pub to: String,
+    pub resource: Resource,
+    pub amount: u32,
+}
+
+impl Display for Transfer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[t={}] {} gave {} to {}",
+            self.time,
+            self.from,
+            self.resource.amount(self.amount),
+            self.to
+        )
+    }
+}
@@ -1,22 +1,77 @@
-use std::io::{stdin, stdout, Write};
+use std::{
+    io::{stdin, stdout, Write},
+    sync::Arc,
+};
 
-use agent::MODEL;
+use backend::ChatOptions;
+use clap::Parser;
+use config::{Args, Command, Config};
+use contract::ContractOutcome;
 use environment::Environment;
-use ollama_rs::{
-    generation::chat::{request::ChatMessageRequest, ChatMessage},
-    Ollama,
-};
+use event::JsonlSink;
+use ollama_rs::generation::chat::ChatMessage;
 use signalbool::{Flag, Signal, SignalBool};
 
 mod action;
 mod agent;
+mod analytics;
+mod backend;
+mod batch;
+mod config;
+mod contract;
+mod conversation;
 mod environment;
+mod episodes;
+mod event;
+mod governance;
+mod inheritance;
+mod ledger;
+mod market;
+mod meeting;
+mod names;
+mod relationships;
+mod snapshot;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let ollama = Ollama::default();
+    let args = Args::parse();
+
+    if let Some(Command::Batch(batch)) = &args.command {
+        if args.resume.is_some() {
+            anyhow::bail!("--resume can't be used with batch");
+        }
+        return batch::run(Config::load(&args)?, batch).await;
+    }
 
-    let mut env = Environment::create(ollama, 4);
+    let mut env = match &args.resume {
+        Some(path) => {
+            let mut env = snapshot::load(path)?;
+
+            let mut config = (*env.config).clone();
+            config.apply(&args);
+            config.validate()?;
+            let config = Arc::new(config);
+
+            env.attach(backend::from_config(&config)?, config);
+
+            println!("Resuming from step {}", env.time());
+            env
+        }
+        None => {
+            let config = Arc::new(Config::load(&args)?);
+            let backend = backend::from_config(&config)?;
+
+            Environment::create(backend, config)
+        }
+    };
+
+    if env.config.events.enabled {
+        // a resumed run carries on the log it started
+        let sink = JsonlSink::open(&env.config.events.path, args.resume.is_some())?;
+        env.set_sink(Box::new(sink));
+    }
+
+    println!("Seed: {}", env.config.seed());
 
     println!("Let's meet our cast!");
 
@@ -31,9 +86,20 @@ async fn main() -> anyhow::Result<()> {
     }
 
     let mut sb = SignalBool::new(&[Signal::SIGINT], Flag::Restart)?;
+    let snapshot = env.config.snapshot.clone();
 
     loop {
-        env.run_timestep().await?;
+        if let Err(e) = env.run_timestep().await {
+            // the step may be half done, but that beats losing the whole run
+            if snapshot.every > 0 {
+                snapshot::save(&env, &snapshot.path)?;
+            }
+            return Err(e);
+        }
+
+        if snapshot.every > 0 && env.time() % snapshot.every == 0 {
+            snapshot::save(&env, &snapshot.path)?;
+        }
 
         if sb.caught() {
             sb.reset();
@@ -43,7 +109,7 @@ async fn main() -> anyhow::Result<()> {
             println!();
 
             if sb.caught() {
-                return Ok(());
+                break;
             }
         }
 
@@ -52,6 +118,47 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    if snapshot.every > 0 {
+        snapshot::save(&env, &snapshot.path)?;
+    }
+
+    println!("Food price history:");
+    for p in &env.market.price_history {
+        println!("\tstep {}: ${} ({} sold)", p.time, p.price, p.volume);
+    }
+
+    println!("Contracts:");
+    for outcome in [
+        ContractOutcome::Executed,
+        ContractOutcome::Rejected,
+        ContractOutcome::Expired,
+    ] {
+        let count = env
+            .contracts
+            .closed
+            .iter()
+            .filter(|(_, o)| *o == outcome)
+            .count();
+        println!("\t{outcome:?}: {count}");
+    }
+
+    println!("Community pool: {}", env.pool);
+
+    println!("Traits by generation:");
+    for line in analytics::describe_generations(&env.lineage).lines() {
+        println!("\t{line}");
+    }
+
+    if let Some(path) = &env.config.lineage.family_tree {
+        analytics::export_family_tree(&env.lineage, path)?;
+        println!("[INFO] Wrote family tree to {}", path.display());
+    }
+
+    println!("Invalid responses per person:");
+    for (name, errors) in env.error_report() {
+        println!("\t{name}: {errors}");
+    }
+
     Ok(())
 }
 
@@ -60,20 +167,15 @@ async fn cli(env: &mut Environment) -> Option<()> {
 
     println!("Who do you want to chat with? [{}]", names.join(", "));
     let mut lines = stdin().lines();
-    let mut name;
     let mut agent = loop {
-        name = lines.next()?.ok()?;
+        let query = lines.next()?.ok()?;
 
-        if let Some(a) = env
-            .agents
-            .iter()
-            .find(|a| a.name.to_lowercase() == name.to_lowercase())
-        {
-            break a.to_owned();
+        match env.resolve(&query) {
+            Ok(id) => break env.agents[env.index_of(id)?].to_owned(),
+            Err(e) => println!("{e}"),
         }
-
-        println!("Invalid name");
     };
+    let name = agent.name.clone();
 
     println!("Talking to {}", agent.name);
 
@@ -84,15 +186,22 @@ async fn cli(env: &mut Environment) -> Option<()> {
         let line = line.ok()?;
 
         let res = agent
-            .ollama
-            .send_chat_messages_with_history(
+            .backend
+            .chat_with_history(
                 &mut agent.history,
-                ChatMessageRequest::new(MODEL.to_string(), vec![ChatMessage::user(line)]),
+                ChatMessage::user(line),
+                &ChatOptions::from_config(&agent.config),
             )
-            .await
-            .unwrap();
-
-        println!("{}> {}", name, res.message.content);
+            .await;
+
+        // a failed request shouldn't take the exit snapshot down with it
+        match res {
+            Ok(res) => println!("{}> {}", name, res),
+            Err(e) => {
+                println!("[INFO] {name} couldn't answer: {e:#}");
+                agent.history.pop();
+            }
+        }
 
         print!("> ");
         stdout().flush().ok()?;
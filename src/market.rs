@@ -0,0 +1,371 @@
+use std::{collections::HashMap, fmt::Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{agent::AgentId, ledger::Resource};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    // selling food for money
+    Offer,
+    // buying food with money
+    Bid,
+}
+
+impl Side {
+    // what an order on this side holds in escrow
+    pub fn escrowed(&self) -> Resource {
+        match self {
+            Side::Offer => Resource::Food,
+            Side::Bid => Resource::Money,
+        }
+    }
+}
+
+/// A standing order to buy or sell food. Whatever is being traded
+/// away is held by the market until the order fills or expires
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Order {
+    pub id: u32,
+    pub side: Side,
+    pub owner: AgentId,
+    pub amount: u32,
+    // dollars per food
+    pub price: u32,
+    pub posted: u32,
+}
+
+impl Order {
+    // what the market is holding on the owner's behalf
+    pub fn escrow(&self) -> u32 {
+        Self::escrow_for(self.side, self.amount, self.price).unwrap_or(u32::MAX)
+    }
+
+    /// What posting an order would put in escrow, or None if it's more than
+    /// anyone could ever have. Such orders are never posted, so `escrow`
+    /// always fits
+    pub fn escrow_for(side: Side, amount: u32, price: u32) -> Option<u32> {
+        match side {
+            Side::Offer => Some(amount),
+            Side::Bid => amount.checked_mul(price),
+        }
+    }
+}
+
+/// A matched bid and offer, to be settled at `price`
+#[derive(Clone, Debug)]
+pub struct Fill {
+    pub seller: AgentId,
+    pub buyer: AgentId,
+    pub amount: u32,
+    pub price: u32,
+    // what the buyer escrowed per food, so the difference can be refunded
+    pub bid_price: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PricePoint {
+    pub time: u32,
+    pub price: u32,
+    pub volume: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Market {
+    next_id: u32,
+    pub orders: Vec<Order>,
+    pub price_history: Vec<PricePoint>,
+}
+
+impl Market {
+    pub fn post(&mut self, side: Side, owner: AgentId, amount: u32, price: u32, time: u32) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.orders.push(Order {
+            id,
+            side,
+            owner,
+            amount,
+            price,
+            posted: time,
+        });
+
+        id
+    }
+
+    /// Whether a new order would match one of `owner`'s own orders on the
+    /// other side of the book
+    pub fn crosses_own(&self, owner: AgentId, side: Side, price: u32) -> bool {
+        self.orders.iter().any(|o| {
+            o.owner == owner
+                && match (side, o.side) {
+                    (Side::Bid, Side::Offer) => price >= o.price,
+                    (Side::Offer, Side::Bid) => o.price >= price,
+                    _ => false,
+                }
+        })
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Order> {
+        self.orders.iter().find(|o| o.id == id)
+    }
+
+    /// Takes `amount` food off an offer, removing it once empty
+    pub fn take(&mut self, id: u32, amount: u32) {
+        if let Some(pos) = self.orders.iter().position(|o| o.id == id) {
+            self.orders[pos].amount -= amount;
+            if self.orders[pos].amount == 0 {
+                self.orders.remove(pos);
+            }
+        }
+    }
+
+    /// Runs a call auction over the book. Every match trades at a single
+    /// clearing price, halfway between the last bid and offer to cross.
+    /// Nobody is sold more food than `room` says they can hold; the rest of
+    /// their bid stays on the book
+    pub fn clear(&mut self, time: u32, room: impl Fn(AgentId) -> u32) -> Vec<Fill> {
+        let mut bids: Vec<_> = self
+            .orders
+            .iter()
+            .filter(|o| o.side == Side::Bid)
+            .cloned()
+            .collect();
+        let mut offers: Vec<_> = self
+            .orders
+            .iter()
+            .filter(|o| o.side == Side::Offer)
+            .cloned()
+            .collect();
+        bids.sort_by_key(|o| (std::cmp::Reverse(o.price), o.id));
+        offers.sort_by_key(|o| (o.price, o.id));
+
+        let mut matches = vec![];
+        let mut room_left: HashMap<AgentId, u32> = HashMap::new();
+        let (mut b, mut o) = (0, 0);
+        while b < bids.len() && o < offers.len() && bids[b].price >= offers[o].price {
+            let buyer = bids[b].owner;
+            let left = *room_left.entry(buyer).or_insert_with(|| room(buyer));
+            let amount = bids[b].amount.min(offers[o].amount).min(left);
+            if amount == 0 {
+                b += 1;
+                continue;
+            }
+            room_left.insert(buyer, left - amount);
+            matches.push((b, o, amount));
+
+            bids[b].amount -= amount;
+            offers[o].amount -= amount;
+            if bids[b].amount == 0 {
+                b += 1;
+            }
+            if offers[o].amount == 0 {
+                o += 1;
+            }
+        }
+
+        let Some(&(last_b, last_o, _)) = matches.last() else {
+            return vec![];
+        };
+        // the bid is at least the offer, so this can't overflow
+        let (bid, offer) = (bids[last_b].price, offers[last_o].price);
+        let price = offer + (bid - offer) / 2;
+
+        let fills: Vec<_> = matches
+            .into_iter()
+            .map(|(b, o, amount)| Fill {
+                seller: offers[o].owner,
+                buyer: bids[b].owner,
+                amount,
+                price,
+                bid_price: bids[b].price,
+            })
+            .collect();
+
+        self.orders = bids
+            .into_iter()
+            .chain(offers)
+            .filter(|o| o.amount > 0)
+            .collect();
+        self.orders.sort_by_key(|o| o.id);
+
+        self.price_history.push(PricePoint {
+            time,
+            price,
+            volume: fills.iter().map(|f| f.amount).sum(),
+        });
+
+        fills
+    }
+
+    /// Removes and returns orders that have sat too long
+    pub fn expire(&mut self, time: u32, lifetime: u32) -> Vec<Order> {
+        let (expired, kept) = self
+            .orders
+            .drain(..)
+            .partition(|o| time - o.posted >= lifetime);
+        self.orders = kept;
+
+        expired
+    }
+
+    /// Removes and returns every order `is_gone` says belongs to someone who
+    /// has left
+    pub fn withdraw(&mut self, is_gone: impl Fn(AgentId) -> bool) -> Vec<Order> {
+        let (gone, kept) = self.orders.drain(..).partition(|o| is_gone(o.owner));
+        self.orders = kept;
+
+        gone
+    }
+
+    /// Human-readable state of the market, for agents' prompts. `name` says
+    /// what to call each order's owner
+    pub fn describe(&self, name: impl Fn(AgentId) -> String) -> String {
+        let mut s = String::new();
+
+        match self.price_history.last() {
+            Some(p) => {
+                let _ = writeln!(
+                    s,
+                    "Food last traded at ${} each (step {}).",
+                    p.price, p.time
+                );
+            }
+            None => {
+                let _ = writeln!(s, "Food has never been traded on the market.");
+            }
+        }
+
+        if self.orders.is_empty() {
+            let _ = writeln!(s, "There are no open orders.");
+        } else {
+            let _ = writeln!(s, "Open orders:");
+            for o in &self.orders {
+                let verb = match o.side {
+                    Side::Offer => "is selling",
+                    Side::Bid => "wants to buy",
+                };
+                let _ = writeln!(
+                    s,
+                    "- #{}: {} {} {} food at ${} each",
+                    o.id,
+                    name(o.owner),
+                    verb,
+                    o.amount,
+                    o.price
+                );
+            }
+        }
+
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANN: AgentId = AgentId(0);
+    const BOB: AgentId = AgentId(1);
+    const CAL: AgentId = AgentId(2);
+
+    fn unlimited(_: AgentId) -> u32 {
+        u32::MAX
+    }
+
+    #[test]
+    fn crossing_orders_fill_at_one_price() {
+        let mut market = Market::default();
+        market.post(Side::Offer, ANN, 3, 2, 0);
+        market.post(Side::Offer, BOB, 3, 4, 0);
+        market.post(Side::Bid, CAL, 4, 6, 0);
+
+        let fills = market.clear(0, unlimited);
+
+        assert_eq!(fills.len(), 2);
+        assert_eq!((fills[0].seller, fills[0].amount), (ANN, 3));
+        assert_eq!((fills[1].seller, fills[1].amount), (BOB, 1));
+        // halfway between the last bid and offer to cross
+        assert!(fills.iter().all(|f| f.price == 5 && f.bid_price == 6));
+
+        assert_eq!(market.orders.len(), 1);
+        assert_eq!((market.orders[0].owner, market.orders[0].amount), (BOB, 2));
+        assert_eq!(market.price_history.last().unwrap().volume, 4);
+    }
+
+    #[test]
+    fn orders_that_dont_cross_stay_on_the_book() {
+        let mut market = Market::default();
+        market.post(Side::Offer, ANN, 1, 5, 0);
+        market.post(Side::Bid, BOB, 1, 4, 0);
+
+        assert!(market.clear(0, unlimited).is_empty());
+        assert_eq!(market.orders.len(), 2);
+        assert!(market.price_history.is_empty());
+    }
+
+    #[test]
+    fn buyers_only_get_what_they_have_room_for() {
+        let mut market = Market::default();
+        market.post(Side::Offer, ANN, 5, 1, 0);
+        market.post(Side::Bid, BOB, 5, 1, 0);
+        market.post(Side::Bid, CAL, 5, 1, 0);
+
+        let fills = market.clear(0, |who| if who == BOB { 2 } else { 0 });
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!((fills[0].buyer, fills[0].amount), (BOB, 2));
+        // the rest of both bids wait for room
+        let bids: Vec<_> = market
+            .orders
+            .iter()
+            .filter(|o| o.side == Side::Bid)
+            .collect();
+        assert_eq!(bids.len(), 2);
+        assert_eq!(bids[0].amount, 3);
+    }
+
+    #[test]
+    fn extreme_prices_dont_overflow() {
+        let mut market = Market::default();
+        market.post(Side::Offer, ANN, 1, u32::MAX - 1, 0);
+        market.post(Side::Bid, BOB, 1, u32::MAX, 0);
+
+        let fills = market.clear(0, unlimited);
+        assert_eq!(fills[0].price, u32::MAX - 1);
+
+        assert_eq!(Order::escrow_for(Side::Bid, 100_000, 100_000), None);
+        assert_eq!(
+            Order::escrow_for(Side::Offer, 100_000, 100_000),
+            Some(100_000)
+        );
+    }
+
+    #[test]
+    fn expire_and_withdraw_remove_orders() {
+        let mut market = Market::default();
+        market.post(Side::Offer, ANN, 1, 1, 0);
+        market.post(Side::Bid, BOB, 1, 1, 3);
+        market.post(Side::Bid, CAL, 1, 1, 3);
+
+        let expired = market.expire(5, 5);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].owner, ANN);
+
+        let gone = market.withdraw(|who| who == CAL);
+        assert_eq!(gone.len(), 1);
+        assert_eq!(market.orders.len(), 1);
+        assert_eq!(market.orders[0].owner, BOB);
+    }
+
+    #[test]
+    fn own_orders_cross() {
+        let mut market = Market::default();
+        market.post(Side::Offer, ANN, 1, 3, 0);
+
+        assert!(market.crosses_own(ANN, Side::Bid, 3));
+        assert!(!market.crosses_own(ANN, Side::Bid, 2));
+        assert!(!market.crosses_own(BOB, Side::Bid, 3));
+    }
+}
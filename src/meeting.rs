@@ -0,0 +1,100 @@
+use serde::Serialize;
+
+use crate::{
+    agent::Agent,
+    conversation::{self, Line},
+};
+
+/// How a meeting voted on a question. Anyone whose answer couldn't be
+/// understood abstained
+#[derive(Serialize, Clone, Debug)]
+pub struct Vote {
+    pub question: String,
+    pub yes: Vec<String>,
+    pub no: Vec<String>,
+    pub abstained: Vec<String>,
+    // more yes than no
+    pub passed: bool,
+}
+
+impl Vote {
+    pub fn describe(&self) -> String {
+        format!(
+            "The vote on '{}' {}: {} yes, {} no, {} abstained.",
+            self.question,
+            if self.passed { "passed" } else { "failed" },
+            self.yes.len(),
+            self.no.len(),
+            self.abstained.len()
+        )
+    }
+}
+
+/// Holds a meeting chaired by `agents[0]`, who opens with `agenda`. Everyone
+/// then speaks in turn for `rounds` rounds, and votes on `question` if there
+/// is one. Someone whose turn can't be understood just stays quiet
+pub async fn run(
+    mut agents: Vec<&mut Agent>,
+    agenda: &str,
+    rounds: u32,
+    question: Option<String>,
+) -> anyhow::Result<(Vec<Line>, Option<Vote>)> {
+    let chair = agents[0].name.clone();
+    println!("[DEBUG] {chair} called a meeting: {agenda}");
+    let mut lines = vec![Line {
+        speaker: chair.clone(),
+        message: agenda.to_string(),
+    }];
+
+    for _ in 0..rounds {
+        for agent in agents.iter_mut() {
+            let said = agent
+                .speak(&chair, agenda, &conversation::transcript(&lines))
+                .await?;
+            if let Some(message) = said {
+                println!("[DEBUG] {} (meeting): {message}", agent.name);
+                lines.push(Line {
+                    speaker: agent.name.clone(),
+                    message,
+                });
+            }
+        }
+    }
+
+    let Some(question) = question else {
+        return Ok((lines, None));
+    };
+
+    let context = format!(
+        "At the meeting {chair} called about '{agenda}':\n{}",
+        conversation::transcript(&lines)
+    );
+
+    let vote = poll(agents, question, &context).await?;
+    Ok((lines, Some(vote)))
+}
+
+/// Asks each of `agents` to vote yes or no on `question`, after `context`
+pub async fn poll(
+    agents: impl IntoIterator<Item = &mut Agent>,
+    question: String,
+    context: &str,
+) -> anyhow::Result<Vote> {
+    let (mut yes, mut no, mut abstained) = (vec![], vec![], vec![]);
+    for agent in agents {
+        match agent.vote(&question, context).await? {
+            Some(true) => yes.push(agent.name.clone()),
+            Some(false) => no.push(agent.name.clone()),
+            None => abstained.push(agent.name.clone()),
+        }
+    }
+
+    let passed = yes.len() > no.len();
+    Ok(Vote {
+        question,
+        yes,
+        no,
+        abstained,
+        passed,
+    })
+}
@@ -0,0 +1,178 @@
+/// Case- and whitespace-insensitive form of a name, for comparing
+pub fn normalize(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// `base`, or `base 2`, `base 3`... if it's already taken
+pub fn unique(base: String, taken: impl Fn(&str) -> bool) -> String {
+    if !taken(&base) {
+        return base;
+    }
+
+    (2..)
+        .map(|n| format!("{base} {n}"))
+        .find(|name| !taken(name))
+        .unwrap()
+}
+
+/// Works out who `query` means out of `names`, returning their index. An
+/// exact match (ignoring case and spacing) wins, then a unique prefix, then
+/// the closest name within a couple of typos. Someone in `departed` is never
+/// guessed at, so asking for them is an error. The error explains what went
+/// wrong, for whoever asked
+pub fn resolve(query: &str, names: &[String], departed: &[String]) -> Result<usize, String> {
+    let q = normalize(query);
+    if q.is_empty() {
+        return Err("No name was given".to_string());
+    }
+
+    let normalized: Vec<_> = names.iter().map(|n| normalize(n)).collect();
+
+    if let Some(i) = normalized.iter().position(|n| *n == q) {
+        return Ok(i);
+    }
+
+    // nobody living is called that, so this can only mean them
+    if let Some(gone) = departed.iter().find(|n| normalize(n) == q) {
+        return Err(format!(
+            "{gone} has died or been exiled and is no longer in the community"
+        ));
+    }
+
+    let prefixed: Vec<_> = (0..names.len())
+        .filter(|i| normalized[*i].starts_with(&q))
+        .collect();
+    match prefixed.as_slice() {
+        [i] => return Ok(*i),
+        [] => {}
+        many => return Err(ambiguous(query, names, many)),
+    }
+
+    // allow roughly one typo per four letters, and none in very short names
+    // where one typo is enough to make it someone else's
+    let max_distance = q.chars().count().saturating_sub(1) / 4;
+    let distances: Vec<_> = normalized.iter().map(|n| distance(&q, n)).collect();
+    let best = distances.iter().copied().min().unwrap_or(usize::MAX);
+    if best > max_distance {
+        return Err(format!("There is nobody called '{query}' in the community"));
+    }
+
+    let closest: Vec<_> = (0..names.len()).filter(|i| distances[*i] == best).collect();
+    match closest.as_slice() {
+        [i] => Ok(*i),
+        many => Err(ambiguous(query, names, many)),
+    }
+}
+
+fn ambiguous(query: &str, names: &[String], matches: &[usize]) -> String {
+    let matches: Vec<_> = matches.iter().map(|i| names[*i].as_str()).collect();
+    format!(
+        "'{query}' could mean any of {}. Use their full name",
+        matches.join(", ")
+    )
+}
+
+// Levenshtein distance
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<_> = b.chars().collect();
+    let mut prev: Vec<_> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != *cb);
+            row.push(substitute.min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roster(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    // the name `query` resolves to
+    fn resolve<'a>(
+        query: &str,
+        names: &'a [String],
+        departed: &[String],
+    ) -> Result<&'a str, String> {
+        super::resolve(query, names, departed).map(|i| names[i].as_str())
+    }
+
+    #[test]
+    fn exact_match_ignores_case_and_spacing() {
+        let names = roster(&["Mary Ann", "Joe"]);
+        assert_eq!(resolve("  mary   ANN ", &names, &[]).unwrap(), "Mary Ann");
+        assert_eq!(resolve("joe", &names, &[]).unwrap(), "Joe");
+    }
+
+    #[test]
+    fn exact_match_beats_prefix() {
+        let names = roster(&["Jo", "Joe"]);
+        assert_eq!(resolve("Jo", &names, &[]).unwrap(), "Jo");
+    }
+
+    #[test]
+    fn unique_prefix_matches() {
+        let names = roster(&["Juliana", "Gussie"]);
+        assert_eq!(resolve("jul", &names, &[]).unwrap(), "Juliana");
+    }
+
+    #[test]
+    fn ambiguous_prefix_is_an_error() {
+        let names = roster(&["Jonas", "Jonathan"]);
+        let err = resolve("Jon", &names, &[]).unwrap_err();
+        assert!(err.contains("Jonas") && err.contains("Jonathan"), "{err}");
+    }
+
+    #[test]
+    fn small_typo_in_a_long_name_matches() {
+        let names = roster(&["Gussie", "Juliana"]);
+        assert_eq!(resolve("Gusie", &names, &[]).unwrap(), "Gussie");
+    }
+
+    #[test]
+    fn short_names_need_no_typos() {
+        let names = roster(&["Joe"]);
+        assert!(resolve("Jon", &names, &[]).is_err());
+    }
+
+    #[test]
+    fn equally_close_names_are_ambiguous() {
+        let names = roster(&["Marion", "Marian"]);
+        assert!(resolve("Marien", &names, &[]).is_err());
+    }
+
+    #[test]
+    fn departed_names_are_never_guessed_at() {
+        let names = roster(&["Jonathan"]);
+        let departed = roster(&["Jon"]);
+        let err = resolve("jon", &names, &departed).unwrap_err();
+        assert!(err.contains("Jon has died"), "{err}");
+    }
+
+    #[test]
+    fn nobody_and_empty_are_errors() {
+        let names = roster(&["Joe"]);
+        assert!(resolve("Bartholomew", &names, &[]).is_err());
+        assert!(resolve("   ", &names, &[]).is_err());
+    }
+
+    #[test]
+    fn unique_suffixes_taken_names() {
+        let taken = roster(&["Joe", "Joe 2"]);
+        let is_taken = |n: &str| taken.iter().any(|t| t == n);
+        assert_eq!(unique("Joe".into(), is_taken), "Joe 3");
+        assert_eq!(unique("Ann".into(), is_taken), "Ann");
+    }
+}
@@ -0,0 +1,173 @@
+use std::{collections::BTreeMap, fmt::Write, fs, path::Path};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::agent::AgentId;
+
+/// How one agent feels about another. Both run from -1 to 1 and start at 0
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Relationship {
+    // do they keep their word?
+    pub trust: f32,
+    // do I like them?
+    pub affinity: f32,
+}
+
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphFormat {
+    Dot,
+    #[value(name = "graphml")]
+    GraphMl,
+}
+
+impl GraphFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::GraphMl => "graphml",
+        }
+    }
+}
+
+/// Directed graph of relationships: `edges[a][b]` is how a feels about b.
+/// Pairs that have never interacted have no edge
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RelationshipGraph {
+    edges: BTreeMap<AgentId, BTreeMap<AgentId, Relationship>>,
+}
+
+impl RelationshipGraph {
+    pub fn adjust(&mut self, from: AgentId, to: AgentId, trust: f32, affinity: f32) {
+        if from == to {
+            return;
+        }
+
+        let r = self.edges.entry(from).or_default().entry(to).or_default();
+        r.trust = (r.trust + trust).clamp(-1.0, 1.0);
+        r.affinity = (r.affinity + affinity).clamp(-1.0, 1.0);
+    }
+
+    pub fn get(&self, from: AgentId, to: AgentId) -> Relationship {
+        self.edges
+            .get(&from)
+            .and_then(|e| e.get(&to))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Drops everything to and from someone who has died
+    pub fn forget(&mut self, id: AgentId) {
+        self.edges.remove(&id);
+        for e in self.edges.values_mut() {
+            e.remove(&id);
+        }
+    }
+
+    /// How `me` feels about everyone else in `roster`, for their prompt
+    pub fn describe(&self, me: AgentId, roster: &[(AgentId, String)]) -> String {
+        let mut s = "How you feel about the others (-1 to 1):\n".to_string();
+        for (other, name) in roster.iter().filter(|(id, _)| *id != me) {
+            let r = self.get(me, *other);
+            writeln!(
+                s,
+                "- {name}: trust {:+.2}, affinity {:+.2}",
+                r.trust, r.affinity
+            )
+            .unwrap();
+        }
+        s
+    }
+
+    // `roster` are the nodes, so people nobody knows yet still show up
+    pub fn to_dot(&self, roster: &[(AgentId, String)]) -> String {
+        let mut s = "digraph relationships {\n".to_string();
+        for (_, name) in roster {
+            writeln!(s, "    \"{name}\";").unwrap();
+        }
+        for (from, to, r) in self.named(roster) {
+            writeln!(
+                s,
+                "    \"{from}\" -> \"{to}\" [trust={:.2}, affinity={:.2}, label=\"{:+.2}/{:+.2}\"];",
+                r.trust, r.affinity, r.trust, r.affinity
+            )
+            .unwrap();
+        }
+        s.push_str("}\n");
+        s
+    }
+
+    pub fn to_graphml(&self, roster: &[(AgentId, String)]) -> String {
+        let mut s = concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"trust\" for=\"edge\" attr.name=\"trust\" attr.type=\"float\"/>\n",
+            "  <key id=\"affinity\" for=\"edge\" attr.name=\"affinity\" attr.type=\"float\"/>\n",
+            "  <graph id=\"relationships\" edgedefault=\"directed\">\n",
+        )
+        .to_string();
+        for (_, name) in roster {
+            writeln!(s, "    <node id=\"{}\"/>", xml_escape(name)).unwrap();
+        }
+        for (from, to, r) in self.named(roster) {
+            writeln!(
+                s,
+                "    <edge source=\"{}\" target=\"{}\"><data key=\"trust\">{}</data><data key=\"affinity\">{}</data></edge>",
+                xml_escape(from),
+                xml_escape(to),
+                r.trust,
+                r.affinity
+            )
+            .unwrap();
+        }
+        s.push_str("  </graph>\n</graphml>\n");
+        s
+    }
+
+    /// Writes the graph to `dir` as `step-<time>.<dot|graphml>`
+    pub fn export(
+        &self,
+        roster: &[(AgentId, String)],
+        dir: &Path,
+        time: u32,
+        format: GraphFormat,
+    ) -> anyhow::Result<()> {
+        fs::create_dir_all(dir)?;
+
+        let contents = match format {
+            GraphFormat::Dot => self.to_dot(roster),
+            GraphFormat::GraphMl => self.to_graphml(roster),
+        };
+        fs::write(
+            dir.join(format!("step-{time:05}.{}", format.extension())),
+            contents,
+        )?;
+
+        Ok(())
+    }
+
+    // every edge between people in `roster`, by name
+    fn named<'a>(
+        &'a self,
+        roster: &'a [(AgentId, String)],
+    ) -> impl Iterator<Item = (&'a str, &'a str, &'a Relationship)> {
+        let name = |id: &AgentId| {
+            roster
+                .iter()
+                .find(|(r, _)| r == id)
+                .map(|(_, name)| name.as_str())
+        };
+        self.edges.iter().flat_map(move |(from, e)| {
+            e.iter()
+                .filter_map(move |(to, r)| Some((name(from)?, name(to)?, r)))
+        })
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
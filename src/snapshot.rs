@@ -0,0 +1,54 @@
+use std::{fs, path::Path};
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::environment::Environment;
+
+// bump whenever the saved state changes shape
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    version: u32,
+    environment: &'a Environment,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    version: u32,
+    environment: Environment,
+}
+
+/// Writes the whole simulation to `path`. Goes through a temporary file so a
+/// crash mid-write can't clobber the previous snapshot
+pub fn save(env: &Environment, path: &Path) -> anyhow::Result<()> {
+    let json = serde_json::to_string(&SnapshotRef {
+        version: SNAPSHOT_VERSION,
+        environment: env,
+    })?;
+
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, json)?;
+    fs::rename(tmp, path)?;
+
+    println!("[INFO] Saved snapshot to {}", path.display());
+
+    Ok(())
+}
+
+/// Loads a simulation saved by `save`. Agents come back without a backend,
+/// so `Environment::attach` must be called before running it
+pub fn load(path: &Path) -> anyhow::Result<Environment> {
+    let snapshot: Snapshot = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+    if snapshot.version != SNAPSHOT_VERSION {
+        bail!(
+            "Snapshot is version {} but this build reads version {}",
+            snapshot.version,
+            SNAPSHOT_VERSION
+        );
+    }
+
+    Ok(snapshot.environment)
+}